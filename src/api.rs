@@ -0,0 +1,570 @@
+use std::error::Error;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use clap::ValueEnum;
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, CONTENT_TYPE, ETAG, IF_NONE_MATCH, LINK};
+use reqwest::{Client, Response, StatusCode, Url};
+use serde_json::{json, Value};
+
+use crate::cache::{Entry, MetadataCache};
+use crate::http;
+
+/// Content type of the git LFS batch API.
+pub const LFS_MEDIA_TYPE: &str = "application/vnd.git-lfs+json";
+
+/// Kind of repository on the Hub, used to build API and resolve urls.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum RepoType {
+    #[default]
+    Model,
+    Dataset,
+    Space,
+}
+
+impl RepoType {
+    /// Path segment used by the `/api/...` routes, e.g. `models`.
+    pub fn api_segment(&self) -> &'static str {
+        match self {
+            RepoType::Model => "models",
+            RepoType::Dataset => "datasets",
+            RepoType::Space => "spaces",
+        }
+    }
+
+    /// Singular name used by query parameters, e.g. `type=model`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RepoType::Model => "model",
+            RepoType::Dataset => "dataset",
+            RepoType::Space => "space",
+        }
+    }
+
+    /// Prefix used by the web/resolve routes, models have none.
+    pub fn url_prefix(&self) -> &'static str {
+        match self {
+            RepoType::Model => "",
+            RepoType::Dataset => "datasets/",
+            RepoType::Space => "spaces/",
+        }
+    }
+}
+
+/// Thin wrapper over the Hub REST API.
+pub struct HubApi {
+    client: Client,
+    endpoint: Url,
+    token: Option<String>,
+    cache: Option<MetadataCache>,
+}
+
+impl HubApi {
+    pub fn new(endpoint: &str, token: Option<String>) -> Result<Self, Box<dyn Error>> {
+        let endpoint = Url::parse(&if endpoint.ends_with('/') {
+            endpoint.to_string()
+        } else {
+            endpoint.to_string() + "/"
+        })?;
+
+        let mut headers = HeaderMap::new();
+        if let Some(token) = &token {
+            let mut value = HeaderValue::from_str(&format!("Bearer {token}"))?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+        let client = http::client_builder().default_headers(headers).build()?;
+
+        Ok(HubApi { client, endpoint, token, cache: None })
+    }
+
+    /// Serve metadata requests from `cache` when possible.
+    pub fn with_cache(mut self, cache: MetadataCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn has_token(&self) -> bool {
+        self.token.is_some()
+    }
+
+    /// `<endpoint>/api/<type>s/<repo_id>/<rest>`
+    fn repo_url(&self, repo_type: RepoType, repo_id: &str, rest: &str) -> Result<Url, Box<dyn Error>> {
+        let path = if rest.is_empty() {
+            format!("api/{}/{repo_id}", repo_type.api_segment())
+        } else {
+            format!("api/{}/{repo_id}/{rest}", repo_type.api_segment())
+        };
+        Ok(self.endpoint.join(&path)?)
+    }
+
+    /// `<endpoint>/<prefix><repo_id>/resolve/<revision>/<path>`
+    pub fn resolve_url(&self, repo_type: RepoType, repo_id: &str, revision: &str, path: &str) -> Result<Url, Box<dyn Error>> {
+        let mut url = self.endpoint.join(&format!(
+            "{}{repo_id}/resolve/{}",
+            repo_type.url_prefix(),
+            encode_revision(revision)
+        ))?;
+        url.path_segments_mut()
+            .map_err(|_| "Endpoint cannot be a base url")?
+            .extend(path.split('/'));
+        Ok(url)
+    }
+
+    /// Turn error statuses into errors, explaining what the token lacks on 401/403.
+    async fn check(&self, resp: Response, write: bool) -> Result<Response, Box<dyn Error>> {
+        let status = resp.status();
+        if status.is_success() {
+            return Ok(resp);
+        }
+        let url = resp.url().clone();
+        let body = resp.text().await.unwrap_or_default();
+        let mut detail = serde_json::from_str::<Value>(&body)
+            .ok()
+            .and_then(|v| v["error"].as_str().map(str::to_string))
+            .unwrap_or(body);
+        if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            if let Some((repo_type, repo_id)) = repo_from_path(url.path()) {
+                detail.push('\n');
+                detail.push_str(&self.explain_auth_failure(repo_type, &repo_id, write).await);
+            }
+        }
+        Err(format!("{url} returned {status}: {detail}").into())
+    }
+
+    /// Token details from `whoami-v2`, including fine-grained permissions.
+    pub async fn whoami(&self) -> Result<Value, Box<dyn Error>> {
+        let resp = http::send(self.client.get(self.endpoint.join("api/whoami-v2")?)).await?;
+        if !resp.status().is_success() {
+            return Err(format!("whoami returned {}", resp.status()).into());
+        }
+        Ok(resp.json().await?)
+    }
+
+    /// Human readable reason why the token was refused for `repo_id`.
+    pub async fn explain_auth_failure(&self, repo_type: RepoType, repo_id: &str, write: bool) -> String {
+        if !self.has_token() {
+            return format!(
+                "No token was sent, {repo_id} is private or gated: pass --hf-token{}",
+                if write { " with write permission" } else { "" }
+            );
+        }
+        match self.whoami().await {
+            Ok(whoami) => describe_token(&whoami, repo_type, repo_id, write),
+            Err(_) => "The token was rejected by whoami, it is invalid or expired.".to_string(),
+        }
+    }
+
+    async fn get_json(&self, url: Url) -> Result<Value, Box<dyn Error>> {
+        Ok(self.get_page(url).await?.0)
+    }
+
+    /// A json response and the `Link: rel="next"` page after it, if any.
+    async fn get_page(&self, url: Url) -> Result<(Value, Option<Url>), Box<dyn Error>> {
+        let token = self.token.as_deref();
+        let cached = self.cache.as_ref().and_then(|c| c.get(url.as_str(), token));
+        let next_of = |entry: &Entry| entry.next.as_deref().and_then(|l| Url::parse(l).ok());
+        if let (Some(cache), Some(entry)) = (&self.cache, &cached) {
+            if cache.is_fresh(entry) {
+                return Ok((entry.body.clone(), next_of(entry)));
+            }
+        }
+
+        let mut req = self.client.get(url.clone());
+        if let Some(etag) = cached.as_ref().and_then(|e| e.etag.as_deref()) {
+            req = req.header(IF_NONE_MATCH, etag);
+        }
+        let resp = http::send(req).await?;
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            if let (Some(cache), Some(entry)) = (&self.cache, cached) {
+                cache.put(url.as_str(), token, entry.etag.as_deref(), entry.next.as_deref(), &entry.body);
+                let next = next_of(&entry);
+                return Ok((entry.body, next));
+            }
+        }
+
+        let resp = self.check(resp, false).await?;
+        let etag = resp.headers().get(ETAG).and_then(|v| v.to_str().ok()).map(str::to_string);
+        let next = resp.headers()
+            .get(LINK)
+            .and_then(|v| v.to_str().ok())
+            .and_then(next_link);
+        let body: Value = resp.json().await?;
+        if let Some(cache) = &self.cache {
+            cache.put(url.as_str(), token, etag.as_deref(), next.as_deref(), &body);
+        }
+        Ok((body, next.and_then(|l| Url::parse(&l).ok())))
+    }
+
+    /// Concatenate the json arrays of every page starting at `url`, stopping
+    /// once `limit` items are collected.
+    async fn get_list(&self, url: Url, limit: Option<usize>) -> Result<Vec<Value>, Box<dyn Error>> {
+        let mut items = Vec::new();
+        let mut next = Some(url);
+        while let Some(url) = next.take() {
+            let (page, next_page) = self.get_page(url.clone()).await?;
+            match page {
+                Value::Array(page) => items.extend(page),
+                other => return Err(format!("Unexpected response from {url}: {other}").into()),
+            }
+            next = next_page;
+            if let Some(limit) = limit {
+                if items.len() >= limit {
+                    items.truncate(limit);
+                    break;
+                }
+            }
+        }
+        Ok(items)
+    }
+
+    /// List entries of the repository tree at `path`, every page of it.
+    pub async fn list_tree(&self, repo_type: RepoType, repo_id: &str, revision: &str, path: &str, recursive: bool) -> Result<Vec<Value>, Box<dyn Error>> {
+        let mut url = self.repo_url(repo_type, repo_id, &format!("tree/{}/{path}", encode_revision(revision)))?;
+        if recursive {
+            url.query_pairs_mut().append_pair("recursive", "true");
+        }
+        self.get_list(url, None).await
+    }
+
+    /// Commit history of `revision`, newest first, at most `limit` commits.
+    pub async fn list_commits(&self, repo_type: RepoType, repo_id: &str, revision: &str, limit: Option<usize>) -> Result<Vec<Value>, Box<dyn Error>> {
+        let url = self.repo_url(repo_type, repo_id, &format!("commits/{}", encode_revision(revision)))?;
+        self.get_list(url, limit).await
+    }
+
+    /// Repo metadata, with per file sizes (`blobs=true`).
+    pub async fn repo_info(&self, repo_type: RepoType, repo_id: &str, revision: Option<&str>) -> Result<Value, Box<dyn Error>> {
+        let mut url = match revision {
+            Some(revision) => self.repo_url(repo_type, repo_id, &format!("revision/{}", encode_revision(revision)))?,
+            None => self.repo_url(repo_type, repo_id, "")?,
+        };
+        url.query_pairs_mut().append_pair("blobs", "true");
+        self.get_json(url).await
+    }
+
+    /// Branches, tags and converted refs of a repo.
+    pub async fn list_refs(&self, repo_type: RepoType, repo_id: &str, include_prs: bool) -> Result<Value, Box<dyn Error>> {
+        let mut url = self.repo_url(repo_type, repo_id, "refs")?;
+        if include_prs {
+            url.query_pairs_mut().append_pair("include_prs", "1");
+        }
+        self.get_json(url).await
+    }
+
+    /// List repos of a type with the given query (`author`, `search`, `filter`...),
+    /// following the `Link: rel="next"` pagination until `limit` repos are collected.
+    pub async fn list_repos(&self, repo_type: RepoType, query: &[(&str, &str)], limit: Option<usize>) -> Result<Vec<Value>, Box<dyn Error>> {
+        let mut url = self.endpoint.join(&format!("api/{}", repo_type.api_segment()))?;
+        url.query_pairs_mut().extend_pairs(query);
+
+        self.get_list(url, limit).await
+    }
+
+    /// Currently trending repos, all types unless `repo_type` is given.
+    pub async fn trending(&self, repo_type: Option<RepoType>, limit: usize) -> Result<Vec<Value>, Box<dyn Error>> {
+        let mut url = self.endpoint.join("api/trending")?;
+        url.query_pairs_mut().append_pair("limit", &limit.to_string());
+        if let Some(repo_type) = repo_type {
+            url.query_pairs_mut().append_pair("type", repo_type.name());
+        }
+        match self.get_json(url).await?["recentlyTrending"].take() {
+            Value::Array(repos) => Ok(repos),
+            other => Err(format!("Unexpected trending response: {other}").into()),
+        }
+    }
+
+    /// A collection with its items, `slug` is `<owner>/<name>-<id>`.
+    pub async fn collection(&self, slug: &str) -> Result<Value, Box<dyn Error>> {
+        self.get_json(self.endpoint.join(&format!("api/collections/{slug}"))?).await
+    }
+
+    /// Response for a (non LFS) file, to read its headers before its body.
+    pub async fn download(&self, repo_type: RepoType, repo_id: &str, revision: &str, path: &str) -> Result<Response, Box<dyn Error>> {
+        let url = self.resolve_url(repo_type, repo_id, revision, path)?;
+        self.check(http::send(self.client.get(url)).await?, false).await
+    }
+
+    /// Raw bytes of a (non LFS) file.
+    pub async fn download_bytes(&self, repo_type: RepoType, repo_id: &str, revision: &str, path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(self.download(repo_type, repo_id, revision, path).await?.bytes().await?.to_vec())
+    }
+
+    /// Ask the Hub which files must go through LFS, `files` are `(path, size, first 512 bytes)`.
+    /// Returns one `{path, uploadMode, shouldIgnore}` object per file.
+    pub async fn preupload(&self, repo_type: RepoType, repo_id: &str, revision: &str, files: &[(String, u64, Vec<u8>)]) -> Result<Vec<Value>, Box<dyn Error>> {
+        let url = self.repo_url(repo_type, repo_id, &format!("preupload/{}", encode_revision(revision)))?;
+        let files: Vec<_> = files.iter()
+            .map(|(path, size, sample)| json!({"path": path, "size": size, "sample": STANDARD.encode(sample)}))
+            .collect();
+        let resp = http::send(self.client
+            .post(url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(json!({"files": files}).to_string())
+        ).await?;
+        match self.check(resp, true).await?.json::<Value>().await?["files"].take() {
+            Value::Array(files) => Ok(files),
+            other => Err(format!("Unexpected preupload response: {other}").into()),
+        }
+    }
+
+    /// LFS batch `upload` request for `(oid, size)` objects. Objects the Hub
+    /// already has come back without `actions`.
+    pub async fn lfs_batch_upload(&self, repo_type: RepoType, repo_id: &str, revision: &str, objects: &[(String, u64)]) -> Result<Vec<Value>, Box<dyn Error>> {
+        let url = self.endpoint.join(&format!("{}{repo_id}.git/info/lfs/objects/batch", repo_type.url_prefix()))?;
+        let objects: Vec<_> = objects.iter().map(|(oid, size)| json!({"oid": oid, "size": size})).collect();
+        let body = json!({
+            "operation": "upload",
+            "transfers": ["basic", "multipart"],
+            "objects": objects,
+            "hash_algo": "sha256",
+            "ref": {"name": revision},
+        });
+        let resp = http::send(self.client
+            .post(url)
+            .header(ACCEPT, LFS_MEDIA_TYPE)
+            .header(CONTENT_TYPE, LFS_MEDIA_TYPE)
+            .body(body.to_string())
+        ).await?;
+        let objects = match self.check(resp, true).await?.json::<Value>().await?["objects"].take() {
+            Value::Array(objects) => objects,
+            other => return Err(format!("Unexpected LFS batch response: {other}").into()),
+        };
+        if let Some(error) = objects.iter().find(|o| o["error"].is_object()) {
+            return Err(format!("LFS batch refused {}: {}", error["oid"], error["error"]["message"]).into());
+        }
+        Ok(objects)
+    }
+
+    /// Run the `verify` action of an uploaded LFS object.
+    pub async fn lfs_verify(&self, verify: &Value, oid: &str, size: u64) -> Result<(), Box<dyn Error>> {
+        let href = verify["href"].as_str().ok_or("LFS verify action without href")?;
+        let mut req = self.client
+            .post(href)
+            .header(ACCEPT, LFS_MEDIA_TYPE)
+            .header(CONTENT_TYPE, LFS_MEDIA_TYPE)
+            .body(json!({"oid": oid, "size": size}).to_string());
+        for (name, value) in verify["header"].as_object().into_iter().flatten() {
+            req = req.header(name, value.as_str().unwrap_or_default());
+        }
+        self.check(http::send(req).await?, true).await?;
+        Ok(())
+    }
+
+    /// Create a commit from a list of ndjson operations, see `CommitOperation`.
+    pub async fn create_commit(&self, repo_type: RepoType, repo_id: &str, revision: &str, summary: &str, operations: &[CommitOperation]) -> Result<Value, Box<dyn Error>> {
+        let url = self.repo_url(repo_type, repo_id, &format!("commit/{}", encode_revision(revision)))?;
+
+        let mut body = json!({"key": "header", "value": {"summary": summary, "description": ""}}).to_string();
+        for op in operations {
+            body.push('\n');
+            body.push_str(&op.to_ndjson().to_string());
+        }
+
+        let resp = http::send(self.client
+            .post(url)
+            .header(CONTENT_TYPE, "application/x-ndjson")
+            .body(body)
+        ).await?;
+        Ok(self.check(resp, true).await?.json().await?)
+    }
+}
+
+/// One line of the commit ndjson payload.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommitOperation {
+    DeleteFile { path: String },
+    DeleteFolder { path: String },
+    /// Regular file, `content` is base64 encoded.
+    AddFile { path: String, content: String },
+    /// Reference an LFS object that already exists on the Hub.
+    AddLfsFile { path: String, oid: String, size: u64 },
+}
+
+impl CommitOperation {
+    fn to_ndjson(&self) -> Value {
+        match self {
+            CommitOperation::DeleteFile { path } => json!({"key": "deletedFile", "value": {"path": path}}),
+            CommitOperation::DeleteFolder { path } => json!({"key": "deletedFolder", "value": {"path": path}}),
+            CommitOperation::AddFile { path, content } => json!({
+                "key": "file",
+                "value": {"path": path, "content": content, "encoding": "base64"}
+            }),
+            CommitOperation::AddLfsFile { path, oid, size } => json!({
+                "key": "lfsFile",
+                "value": {"path": path, "algo": "sha256", "oid": oid, "size": size}
+            }),
+        }
+    }
+}
+
+/// `(type, repo_id)` of an `/api/<type>s/<owner>/<name>/...` path, or of a
+/// `/[datasets/|spaces/]<owner>/<name>/resolve/...` file path.
+fn repo_from_path(path: &str) -> Option<(RepoType, String)> {
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    let (repo_type, rest) = match segments[..] {
+        ["api", "models", ref rest @ ..] => (RepoType::Model, rest),
+        ["api", "datasets", ref rest @ ..] => (RepoType::Dataset, rest),
+        ["api", "spaces", ref rest @ ..] => (RepoType::Space, rest),
+        ["datasets", ref rest @ ..] if rest.get(2) == Some(&"resolve") => (RepoType::Dataset, rest),
+        ["spaces", ref rest @ ..] if rest.get(2) == Some(&"resolve") => (RepoType::Space, rest),
+        [_, _, "resolve", ..] => (RepoType::Model, &segments[..]),
+        _ => return None,
+    };
+    match rest {
+        [owner, name, ..] => Some((repo_type, format!("{owner}/{name}"))),
+        _ => None,
+    }
+}
+
+/// Explain a `whoami-v2` answer with regard to the permission `repo_id` needs.
+pub fn describe_token(whoami: &Value, repo_type: RepoType, repo_id: &str, write: bool) -> String {
+    let token = &whoami["auth"]["accessToken"];
+    let name = token["displayName"].as_str().unwrap_or("<unnamed>");
+    let user = whoami["name"].as_str().unwrap_or("<unknown>");
+    let gated_hint = format!(
+        "If {repo_id} is gated, make sure {user} accepted its conditions on the repo page.",
+    );
+
+    match token["role"].as_str() {
+        Some("read") if write => format!(
+            "Token `{name}` of {user} is read-only, modifying {repo_id} needs a write or fine-grained token with `repo.write`."
+        ),
+        Some("read") | Some("write") => format!(
+            "Token `{name}` of {user} has {} access to every repo {user} can see. {gated_hint}",
+            token["role"].as_str().unwrap_or_default()
+        ),
+        Some("fineGrained") => {
+            let needed = if write { "repo.write" } else { "repo.content.read" };
+            let owner = repo_id.split('/').next().unwrap_or_default();
+            let fine_grained = &token["fineGrained"];
+            let scoped = fine_grained["scoped"].as_array().cloned().unwrap_or_default();
+            // "Read access to contents of all public gated repos you can access".
+            let gated_read = fine_grained["canReadGatedRepos"].as_bool().unwrap_or(false);
+
+            let mut granted = Vec::new();
+            let mut covered = false;
+            for scope in &scoped {
+                let kind = scope["entity"]["type"].as_str().unwrap_or_default();
+                let entity = scope["entity"]["name"].as_str().unwrap_or_default();
+                let permissions: Vec<_> = scope["permissions"].as_array()
+                    .map(|p| p.iter().filter_map(Value::as_str).collect())
+                    .unwrap_or_default();
+                let matches = (kind == repo_type.name() && entity == repo_id)
+                    || ((kind == "user" || kind == "org") && entity == owner);
+                if matches && permissions.contains(&needed) {
+                    covered = true;
+                }
+                granted.push(format!("{kind} {entity}: {}", permissions.join(", ")));
+            }
+            let granted = if granted.is_empty() { "none".to_string() } else { granted.join("; ") };
+
+            match (covered, write || gated_read) {
+                (true, true) => format!("Fine-grained token `{name}` of {user} grants `{needed}` on {repo_id}. {gated_hint}"),
+                (true, false) => format!(
+                    "Fine-grained token `{name}` of {user} grants `{needed}` on {repo_id} but lacks gated access: \
+                     if {repo_id} is gated, enable \"Read access to contents of all public gated repos you can access\" on the token."
+                ),
+                (false, true) => format!(
+                    "Fine-grained token `{name}` of {user} is missing `{needed}` for {repo_id} (or its owner {owner}). \
+                     Scopes granted: {granted}. Edit the token permissions in your Hugging Face settings."
+                ),
+                (false, false) => format!(
+                    "Fine-grained token `{name}` of {user} is missing `{needed}` for {repo_id} (or its owner {owner}), needed if it is private, \
+                     and gated access (\"Read access to contents of all public gated repos you can access\"), needed if it is gated. \
+                     Scopes granted: {granted}. Edit the token permissions in your Hugging Face settings."
+                ),
+            }
+        }
+        _ => format!("Token of {user} has an unknown role, it may lack access to {repo_id}."),
+    }
+}
+
+/// Extract the `rel="next"` target of a `Link` header.
+fn next_link(header: &str) -> Option<String> {
+    header.split(',')
+        .find(|part| part.contains("rel=\"next\""))
+        .and_then(|part| Some(part[part.find('<')? + 1..part.find('>')?].to_string()))
+}
+
+/// Revisions like `refs/pr/1` must be sent as a single path segment.
+pub fn encode_revision(revision: &str) -> String {
+    revision.replace('/', "%2F")
+}
+
+#[test]
+fn commit_operation_ndjson() {
+    let op = CommitOperation::AddLfsFile { path: "model.safetensors".into(), oid: "abc".into(), size: 42 };
+    assert_eq!(
+        op.to_ndjson(),
+        json!({"key": "lfsFile", "value": {"path": "model.safetensors", "algo": "sha256", "oid": "abc", "size": 42}})
+    );
+    assert_eq!(encode_revision("refs/pr/1"), "refs%2Fpr%2F1");
+}
+
+#[test]
+fn fine_grained_token_missing_scope() {
+    let whoami = json!({
+        "name": "alice",
+        "auth": {"accessToken": {
+            "displayName": "ci",
+            "role": "fineGrained",
+            "fineGrained": {"scoped": [
+                {"entity": {"type": "model", "name": "alice/tiny"}, "permissions": ["repo.content.read"]}
+            ]}
+        }}
+    });
+    let msg = describe_token(&whoami, RepoType::Model, "meta-llama/Llama-3.1-8B", false);
+    assert!(msg.contains("missing `repo.content.read` for meta-llama/Llama-3.1-8B"), "{msg}");
+    assert!(msg.contains("and gated access"), "{msg}");
+    let msg = describe_token(&whoami, RepoType::Model, "alice/tiny", false);
+    assert!(msg.contains("grants `repo.content.read` on alice/tiny but lacks gated access"), "{msg}");
+
+    let mut whoami = whoami;
+    whoami["auth"]["accessToken"]["fineGrained"]["canReadGatedRepos"] = json!(true);
+    let msg = describe_token(&whoami, RepoType::Model, "meta-llama/Llama-3.1-8B", false);
+    assert!(msg.contains("missing `repo.content.read`") && !msg.contains("gated access"), "{msg}");
+
+    assert_eq!(
+        repo_from_path("/api/datasets/alice/data/tree/main"),
+        Some((RepoType::Dataset, "alice/data".to_string()))
+    );
+    assert_eq!(
+        repo_from_path("/datasets/alice/data/resolve/main/train.csv"),
+        Some((RepoType::Dataset, "alice/data".to_string()))
+    );
+    assert_eq!(
+        repo_from_path("/meta-llama/Llama-3.1-8B/resolve/main/config.json"),
+        Some((RepoType::Model, "meta-llama/Llama-3.1-8B".to_string()))
+    );
+    assert_eq!(repo_from_path("/api/whoami-v2"), None);
+}
+
+#[test]
+fn link_header_next() {
+    assert_eq!(
+        next_link(r#"<https://huggingface.co/api/models?author=openai&cursor=abc>; rel="next""#).as_deref(),
+        Some("https://huggingface.co/api/models?author=openai&cursor=abc")
+    );
+    assert_eq!(next_link(r#"<https://huggingface.co/api/models?cursor=x>; rel="prev""#), None);
+}
+
+#[tokio::test]
+async fn list_tree_follows_pages() {
+    let dir = std::env::temp_dir().join(format!("hfrs_pages_{}", std::process::id()));
+    let cache = MetadataCache::new(dir.clone(), crate::cache::DEFAULT_TTL, false);
+    // Nothing listens there, every page has to come from the cache.
+    let api = HubApi::new("http://127.0.0.1:9", None).unwrap();
+    let mut first = api.repo_url(RepoType::Model, "a/b", "tree/main/").unwrap();
+    first.query_pairs_mut().append_pair("recursive", "true");
+    let second = "http://127.0.0.1:9/api/models/a/b/tree/main/?recursive=true&cursor=x";
+    cache.put(first.as_str(), None, None, Some(second), &json!([{"path": "a.txt"}, {"path": "b.txt"}]));
+    cache.put(second, None, None, None, &json!([{"path": "c.txt"}]));
+
+    let api = api.with_cache(cache);
+    let tree = api.list_tree(RepoType::Model, "a/b", "main", "", true).await.unwrap();
+    let paths: Vec<_> = tree.iter().filter_map(|e| e["path"].as_str()).collect();
+    assert_eq!(paths, ["a.txt", "b.txt", "c.txt"]);
+    assert_eq!(api.get_list(first, Some(2)).await.unwrap().len(), 2);
+    std::fs::remove_dir_all(dir).unwrap();
+}
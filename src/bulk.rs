@@ -0,0 +1,126 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::api::{HubApi, RepoType};
+use crate::{download_repo, wildcard_match, DownloadOptions, DownloadSummary};
+
+/// Accept both a bare slug and a collection url.
+fn parse_slug(input: &str) -> &str {
+    let input = input.trim().trim_end_matches('/');
+    input.split_once("collections/").map_or(input, |(_, slug)| slug)
+}
+
+/// Models and datasets of a collection, papers and spaces can't be downloaded.
+fn downloadable_items(collection: &Value) -> Vec<(RepoType, String)> {
+    collection["items"].as_array()
+        .map(|items| items.iter()
+            .filter_map(|item| {
+                let repo_type = match item["type"].as_str()? {
+                    "model" => RepoType::Model,
+                    "dataset" => RepoType::Dataset,
+                    _ => return None,
+                };
+                Some((repo_type, item["id"].as_str()?.to_string()))
+            })
+            .collect())
+        .unwrap_or_default()
+}
+
+pub async fn download_collection(api: &HubApi, input: &str, endpoint: Option<String>, opts: &DownloadOptions) -> Result<(), Box<dyn Error>> {
+    let slug = parse_slug(input);
+    println!("Fetching collection {slug}...");
+    let collection = api.collection(slug).await?;
+    let items = downloadable_items(&collection);
+    let total = collection["items"].as_array().map_or(0, Vec::len);
+    println!(
+        "Collection `{}` has {} downloadable repo(s), {} other item(s) skipped.",
+        collection["title"].as_str().unwrap_or(slug),
+        items.len(),
+        total - items.len()
+    );
+
+    download_all(api, &items, endpoint, opts).await
+}
+
+/// Download all repos of an organization (or user) whose name matches `pattern`.
+pub async fn org_sync(api: &HubApi, org: &str, repo_type: RepoType, pattern: Option<&str>, endpoint: Option<String>, opts: &DownloadOptions) -> Result<(), Box<dyn Error>> {
+    println!("Listing {} of {org}...", repo_type.api_segment());
+    let repos = api.list_repos(repo_type, &[("author", org)], None).await?;
+    let items: Vec<_> = repos.iter()
+        .filter_map(|r| r["id"].as_str())
+        .filter(|id| pattern.is_none_or(|p| wildcard_match(p, id.rsplit('/').next().unwrap_or(id))))
+        .map(|id| (repo_type, id.to_string()))
+        .collect();
+    println!("{} of {} repo(s) match.", items.len(), repos.len());
+
+    download_all(api, &items, endpoint, opts).await
+}
+
+/// `<base>/[datasets/|spaces/]<owner>`, where a repo of a bulk download is saved.
+/// Repos of different owners or types may share a name, so unlike a single
+/// download the name alone does not identify the directory.
+fn owner_dir(base: &Path, repo_type: RepoType, repo_id: &str) -> PathBuf {
+    let owner = repo_id.split_once('/').map_or("", |(owner, _)| owner);
+    base.join(format!("{}{owner}", repo_type.url_prefix()))
+}
+
+/// Download repos one after another, then print a combined summary.
+async fn download_all(api: &HubApi, items: &[(RepoType, String)], endpoint: Option<String>, opts: &DownloadOptions) -> Result<(), Box<dyn Error>> {
+    let base = match &opts.local_dir {
+        Some(dir) => dir.clone(),
+        None => std::env::current_dir()?,
+    };
+    let mut results: Vec<(String, Result<DownloadSummary, String>)> = Vec::with_capacity(items.len());
+    for (i, (repo_type, repo_id)) in items.iter().enumerate() {
+        println!("[{}/{}] Downloading {repo_id}...", i + 1, items.len());
+        let mut item_opts = opts.clone();
+        item_opts.local_dir = Some(owner_dir(&base, *repo_type, repo_id));
+        let ret = download_repo(api, repo_id, *repo_type, endpoint.clone(), &item_opts)
+            .await
+            .map_err(|e| e.to_string());
+        results.push((repo_id.clone(), ret));
+    }
+
+    println!("Summary:");
+    let mut failed_repos = 0;
+    for (repo_id, ret) in &results {
+        match ret {
+            Ok(s) if s.failed == 0 => println!("  {repo_id}: ok, {} file(s), {} skipped", s.lfs_files + s.small_files, s.skipped),
+            Ok(s) => {
+                failed_repos += 1;
+                println!("  {repo_id}: {} of {} file(s) failed", s.failed, s.lfs_files + s.small_files)
+            }
+            Err(e) => {
+                failed_repos += 1;
+                println!("  {repo_id}: error, {e}")
+            }
+        }
+    }
+
+    if failed_repos > 0 {
+        return Err(format!("{failed_repos} of {} repo(s) did not download completely", results.len()).into());
+    }
+    Ok(())
+}
+
+#[test]
+fn collection_slug_and_items() {
+    assert_eq!(parse_slug("https://huggingface.co/collections/google/gemma-2-release-667d6600fd5220e7b967f315/"), "google/gemma-2-release-667d6600fd5220e7b967f315");
+    assert_eq!(parse_slug("google/gemma-2-release-667d6600fd5220e7b967f315"), "google/gemma-2-release-667d6600fd5220e7b967f315");
+
+    let collection = serde_json::json!({"items": [
+        {"type": "model", "id": "google/gemma-2-2b-it"},
+        {"type": "paper", "id": "2408.00118"},
+        {"type": "dataset", "id": "google/some-data"}
+    ]});
+    assert_eq!(downloadable_items(&collection), vec![
+        (RepoType::Model, "google/gemma-2-2b-it".to_string()),
+        (RepoType::Dataset, "google/some-data".to_string()),
+    ]);
+
+    let base = Path::new("/data");
+    assert_eq!(owner_dir(base, RepoType::Model, "a/foo").join("foo"), Path::new("/data/a/foo"));
+    assert_eq!(owner_dir(base, RepoType::Dataset, "a/foo").join("foo"), Path::new("/data/datasets/a/foo"));
+}
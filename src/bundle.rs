@@ -0,0 +1,358 @@
+use std::error::Error;
+use std::io::SeekFrom;
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::Subcommand;
+use indicatif::{ProgressBar, ProgressStyle};
+use ring::digest::{Context, SHA256};
+use serde_json::{json, Value};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use crate::api::{HubApi, RepoType};
+use crate::git;
+use crate::{download_repo, hex, DownloadOptions, PART_SUFFIX};
+
+/// Last bytes of every bundle. Layout is: file contents back to back, the json
+/// manifest, the manifest length as u64 LE, then this magic. Keeping the
+/// manifest at the end lets `create` stream files without knowing hashes upfront.
+const MAGIC: &[u8; 8] = b"HFBNDL01";
+const TRAILER_LEN: u64 = 16;
+const CHUNK: usize = 1 << 20;
+
+#[derive(Subcommand)]
+pub enum BundleCommands {
+    /// Download a repo and pack it with a manifest into a single file
+    Create {
+        /// Repo to bundle, e.g. `google/gemma-2-2b-it`
+        repo_id: String,
+
+        /// Bundle file to write, e.g. `gemma.hfbundle`
+        #[arg(short, long, value_name = "FILE")]
+        output: PathBuf,
+
+        /// Type of the repo
+        #[arg(long, value_enum, default_value_t)]
+        repo_type: RepoType,
+
+        #[command(flatten)]
+        download: DownloadOptions,
+    },
+
+    /// Verify a bundle and unpack it
+    Extract {
+        /// Bundle file to read
+        bundle: PathBuf,
+
+        /// Directory to unpack into, default is `./<repo name>`
+        #[arg(short, long, value_name = "PATH")]
+        output_dir: Option<PathBuf>,
+    },
+
+    /// Check every file of a bundle against the sha256 of its manifest
+    Verify {
+        /// Bundle file to read
+        bundle: PathBuf,
+    },
+}
+
+impl BundleCommands {
+    pub fn download_options(&mut self) -> Option<&mut DownloadOptions> {
+        match self {
+            BundleCommands::Create { download, .. } => Some(download),
+            _ => None,
+        }
+    }
+}
+
+pub async fn run(command: &BundleCommands, api: &HubApi, endpoint: Option<String>) -> Result<(), Box<dyn Error>> {
+    match command {
+        BundleCommands::Create { repo_id, output, repo_type, download } => {
+            let summary = download_repo(api, repo_id, *repo_type, endpoint, download).await?;
+            if summary.failed > 0 {
+                return Err(format!("{} file(s) failed to download, not bundling an incomplete repo", summary.failed).into());
+            }
+            let revision = revision(api, *repo_type, repo_id, &summary.path, !download.no_git).await;
+            create(&summary.path, repo_id, *repo_type, revision.as_deref(), output).await
+        }
+        BundleCommands::Extract { bundle, output_dir } => {
+            let manifest = read_manifest(bundle).await?;
+            let dir = match output_dir {
+                Some(dir) => dir.clone(),
+                None => {
+                    let repo_id = manifest["repo_id"].as_str().unwrap_or_default();
+                    std::env::current_dir()?.join(repo_id.rsplit('/').next().unwrap_or("bundle"))
+                }
+            };
+            unpack(bundle, &manifest, Some(&dir)).await?;
+            println!("Extracted to {}", dir.display());
+            Ok(())
+        }
+        BundleCommands::Verify { bundle } => {
+            let manifest = read_manifest(bundle).await?;
+            unpack(bundle, &manifest, None).await?;
+            println!("Bundle is intact.");
+            Ok(())
+        }
+    }
+}
+
+/// Every file under `dir`, `.git` and unfinished downloads excluded, as sorted
+/// `/` separated relative paths. Symlinks to directories are not followed.
+pub fn list_files(dir: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in std::fs::read_dir(&current)? {
+            let entry = entry?;
+            let path = entry.path();
+            let rel = path.strip_prefix(dir)?;
+            if rel == Path::new(".git") {
+                continue;
+            }
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if file_type.is_symlink() && path.is_dir() {
+                continue;
+            } else if !path.to_string_lossy().ends_with(PART_SUFFIX) {
+                let parts: Vec<_> = rel.components().map(|c| c.as_os_str().to_string_lossy()).collect();
+                files.push(parts.join("/"));
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+pub fn bytes_bar(len: u64, what: &str) -> ProgressBar {
+    let bar = ProgressBar::new(len);
+    bar.set_style(ProgressStyle::with_template(&(what.to_string() + " {bar:70.green/red} {binary_bytes:>7}/{binary_total_bytes:7} {bytes_per_sec} [{elapsed_precise}/{eta_precise}] {msg}"))
+        .unwrap()
+    );
+    bar
+}
+
+/// Commit of the bundled files as the Hub reports it, falling back to the
+/// clone's HEAD. Without git there is no HEAD: git would find an enclosing repo instead.
+async fn revision(api: &HubApi, repo_type: RepoType, repo_id: &str, dir: &Path, git_mode: bool) -> Option<String> {
+    match api.repo_info(repo_type, repo_id, None).await {
+        Ok(info) if info["sha"].is_string() => return info["sha"].as_str().map(str::to_string),
+        Ok(_) => println!("The Hub reported no revision for {repo_id}"),
+        Err(e) => println!("Cant get the revision of {repo_id}: {e}"),
+    }
+    if !git_mode {
+        return None;
+    }
+    git::git(Some(dir))
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .await
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+}
+
+async fn create(dir: &Path, repo_id: &str, repo_type: RepoType, revision: Option<&str>, output: &Path) -> Result<(), Box<dyn Error>> {
+    let files = list_files(dir)?;
+    let mut total = 0;
+    for file in &files {
+        total += std::fs::metadata(dir.join(file))?.len();
+    }
+    println!("Bundling {} file(s) of {repo_id} into {}...", files.len(), output.display());
+
+    let bar = bytes_bar(total, "bundle");
+    let mut out = File::create(output).await?;
+    let mut entries = Vec::with_capacity(files.len());
+    let mut offset = 0u64;
+    let mut buf = vec![0u8; CHUNK];
+    for file in &files {
+        let mut src = File::open(dir.join(file)).await?;
+        let mut hash = Context::new(&SHA256);
+        let mut size = 0u64;
+        loop {
+            let n = src.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            hash.update(&buf[..n]);
+            out.write_all(&buf[..n]).await?;
+            size += n as u64;
+            bar.inc(n as u64);
+        }
+        entries.push(json!({"path": file, "offset": offset, "size": size, "sha256": hex(hash.finish().as_ref())}));
+        offset += size;
+    }
+    bar.finish();
+
+    let manifest = json!({
+        "format": 1,
+        "repo_id": repo_id,
+        "repo_type": repo_type.name(),
+        "revision": revision,
+        "created": SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        "files": entries,
+    }).to_string();
+    out.write_all(manifest.as_bytes()).await?;
+    out.write_all(&(manifest.len() as u64).to_le_bytes()).await?;
+    out.write_all(MAGIC).await?;
+    out.flush().await?;
+
+    println!("Bundle written: {} file(s), revision {}", files.len(), revision.unwrap_or("unknown"));
+    Ok(())
+}
+
+async fn read_manifest(bundle: &Path) -> Result<Value, Box<dyn Error>> {
+    let mut file = File::open(bundle).await?;
+    let len = file.metadata().await?.len();
+    if len < TRAILER_LEN {
+        return Err(format!("{} is not a bundle", bundle.display()).into());
+    }
+    file.seek(SeekFrom::Start(len - TRAILER_LEN)).await?;
+    let mut trailer = [0u8; TRAILER_LEN as usize];
+    file.read_exact(&mut trailer).await?;
+    let (manifest_len, magic) = trailer.split_at(8);
+    if magic != MAGIC {
+        return Err(format!("{} is not a bundle", bundle.display()).into());
+    }
+    let manifest_len = u64::from_le_bytes(manifest_len.try_into()?);
+    let start = (len - TRAILER_LEN).checked_sub(manifest_len).ok_or("Corrupted bundle trailer")?;
+
+    file.seek(SeekFrom::Start(start)).await?;
+    let mut manifest = vec![0u8; manifest_len as usize];
+    file.read_exact(&mut manifest).await?;
+    let manifest: Value = serde_json::from_slice(&manifest)?;
+    println!(
+        "Bundle of {} {} at revision {}, {} file(s)",
+        manifest["repo_type"].as_str().unwrap_or("model"),
+        manifest["repo_id"].as_str().unwrap_or("?"),
+        manifest["revision"].as_str().unwrap_or("unknown"),
+        manifest["files"].as_array().map_or(0, Vec::len)
+    );
+    Ok(manifest)
+}
+
+/// Reject manifest paths that would escape the output directory.
+fn safe_relative_path(path: &str) -> Option<PathBuf> {
+    let path = Path::new(path);
+    path.components()
+        .all(|c| matches!(c, Component::Normal(_)))
+        .then(|| path.to_path_buf())
+}
+
+/// Hash every file of the bundle, writing them under `dir` when given. Files are
+/// written to a part file first and only get their name once their hash matches.
+async fn unpack(bundle: &Path, manifest: &Value, dir: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let files = manifest["files"].as_array().ok_or("Manifest has no file list")?;
+    let total = files.iter().filter_map(|f| f["size"].as_u64()).sum();
+    let bar = bytes_bar(total, if dir.is_some() { "extract" } else { "verify" });
+
+    let mut src = File::open(bundle).await?;
+    let mut buf = vec![0u8; CHUNK];
+    let mut broken = Vec::new();
+    for entry in files {
+        let (Some(path), Some(offset), Some(size), Some(sha256)) = (
+            entry["path"].as_str(),
+            entry["offset"].as_u64(),
+            entry["size"].as_u64(),
+            entry["sha256"].as_str(),
+        ) else {
+            return Err(format!("Invalid manifest entry {entry}").into());
+        };
+        let rel = safe_relative_path(path).ok_or(format!("Unsafe path in bundle: {path}"))?;
+
+        let mut out = match dir {
+            Some(dir) => {
+                let target = dir.join(rel);
+                if let Some(parent) = target.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                let part = PathBuf::from(format!("{}{PART_SUFFIX}", target.display()));
+                let file = File::create(&part).await?;
+                Some((file, part, target))
+            }
+            None => None,
+        };
+
+        src.seek(SeekFrom::Start(offset)).await?;
+        let mut hash = Context::new(&SHA256);
+        let mut left = size;
+        while left > 0 {
+            let n = src.read(&mut buf[..CHUNK.min(left as usize)]).await?;
+            if n == 0 {
+                return Err(format!("Bundle is truncated inside {path}").into());
+            }
+            hash.update(&buf[..n]);
+            if let Some((out, _, _)) = out.as_mut() {
+                out.write_all(&buf[..n]).await?;
+            }
+            left -= n as u64;
+            bar.inc(n as u64);
+        }
+        let intact = hex(hash.finish().as_ref()) == sha256;
+        if let Some((mut out, part, target)) = out {
+            out.flush().await?;
+            drop(out);
+            if intact {
+                tokio::fs::rename(&part, &target).await?;
+            } else {
+                tokio::fs::remove_file(&part).await?;
+            }
+        }
+        if !intact {
+            broken.push(path.to_string());
+        }
+    }
+    bar.finish();
+
+    if !broken.is_empty() {
+        return Err(format!("sha256 mismatch for {} file(s): {}", broken.len(), broken.join(", ")).into());
+    }
+    Ok(())
+}
+
+#[test]
+fn bundle_paths() {
+    assert_eq!(safe_relative_path("vae/config.json"), Some(PathBuf::from("vae/config.json")));
+    assert_eq!(safe_relative_path("../etc/passwd"), None);
+    assert_eq!(safe_relative_path("/etc/passwd"), None);
+    assert_eq!(
+        hex(ring::digest::digest(&SHA256, b"").as_ref()),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+}
+
+#[tokio::test]
+async fn bundle_roundtrip() {
+    let root = std::env::temp_dir().join(format!("hfrs_bundle_{}", std::process::id()));
+    let repo = root.join("repo");
+    std::fs::create_dir_all(repo.join("vae")).unwrap();
+    std::fs::create_dir_all(repo.join(".git")).unwrap();
+    std::fs::write(repo.join("config.json"), "{}").unwrap();
+    std::fs::write(repo.join("vae/model.bin"), vec![7u8; 3 * CHUNK + 5]).unwrap();
+    std::fs::write(repo.join(".git/HEAD"), "ref: refs/heads/main").unwrap();
+
+    let bundle = root.join("repo.hfbundle");
+    create(&repo, "me/repo", RepoType::Model, Some("0123abcd"), &bundle).await.unwrap();
+    let manifest = read_manifest(&bundle).await.unwrap();
+    assert_eq!(manifest["files"].as_array().unwrap().len(), 2);
+    assert_eq!(manifest["revision"], "0123abcd");
+
+    unpack(&bundle, &manifest, Some(&root.join("out"))).await.unwrap();
+    assert_eq!(std::fs::read(root.join("out/vae/model.bin")).unwrap(), vec![7u8; 3 * CHUNK + 5]);
+    assert!(!root.join("out/.git").exists());
+
+    // Flip one byte of the first file, verification must catch it.
+    let mut bytes = std::fs::read(&bundle).unwrap();
+    bytes[0] ^= 1;
+    std::fs::write(&bundle, bytes).unwrap();
+    assert!(unpack(&bundle, &manifest, None).await.is_err());
+    // A corrupt file is not left behind under its name, nor as a part file.
+    assert!(unpack(&bundle, &manifest, Some(&root.join("bad"))).await.is_err());
+    assert!(!root.join("bad/config.json").exists());
+    assert!(!root.join(format!("bad/config.json{PART_SUFFIX}")).exists());
+    assert!(root.join("bad/vae/model.bin").exists());
+
+    std::fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,90 @@
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ring::digest::{digest, SHA256};
+use serde_json::{json, Value};
+
+use crate::hex;
+
+/// Default time an API response is reused without asking the server again.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(300);
+
+/// On disk cache of API json responses, one file per url and token.
+///
+/// Entries younger than `ttl` are used as is; older ones are revalidated with
+/// `If-None-Match` so an unchanged repo only costs a 304.
+pub struct MetadataCache {
+    dir: PathBuf,
+    ttl: Duration,
+    /// `--refresh`: never answer from the cache, but still store new responses.
+    refresh: bool,
+}
+
+/// A cached response.
+pub struct Entry {
+    pub etag: Option<String>,
+    pub body: Value,
+    /// `Link: rel="next"` of paginated responses.
+    pub next: Option<String>,
+    fetched: u64,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+impl MetadataCache {
+    pub fn new(dir: PathBuf, ttl: Duration, refresh: bool) -> Self {
+        MetadataCache { dir, ttl, refresh }
+    }
+
+    fn path(&self, url: &str, token: Option<&str>) -> PathBuf {
+        let key = format!("{url}\n{}", token.unwrap_or_default());
+        self.dir.join(hex(digest(&SHA256, key.as_bytes()).as_ref()) + ".json")
+    }
+
+    pub fn get(&self, url: &str, token: Option<&str>) -> Option<Entry> {
+        if self.refresh {
+            return None;
+        }
+        let text = std::fs::read_to_string(self.path(url, token)).ok()?;
+        let value: Value = serde_json::from_str(&text).ok()?;
+        Some(Entry {
+            etag: value["etag"].as_str().map(str::to_string),
+            next: value["next"].as_str().map(str::to_string),
+            fetched: value["fetched"].as_u64()?,
+            body: value["body"].clone(),
+        })
+    }
+
+    pub fn is_fresh(&self, entry: &Entry) -> bool {
+        now().saturating_sub(entry.fetched) < self.ttl.as_secs()
+    }
+
+    /// Store a response, failures only cost a request next time so they are ignored.
+    pub fn put(&self, url: &str, token: Option<&str>, etag: Option<&str>, next: Option<&str>, body: &Value) {
+        let entry = json!({"url": url, "etag": etag, "next": next, "fetched": now(), "body": body});
+        if std::fs::create_dir_all(&self.dir).is_ok() {
+            let _ = std::fs::write(self.path(url, token), entry.to_string());
+        }
+    }
+}
+
+#[test]
+fn cache_roundtrip() {
+    let dir = std::env::temp_dir().join(format!("hfrs_cache_{}", std::process::id()));
+    let cache = MetadataCache::new(dir.clone(), DEFAULT_TTL, false);
+    let url = "https://huggingface.co/api/models/google/gemma-2-2b-it";
+
+    assert!(cache.get(url, None).is_none());
+    cache.put(url, None, Some("\"abc\""), None, &json!({"id": "google/gemma-2-2b-it"}));
+    let entry = cache.get(url, None).unwrap();
+    assert_eq!(entry.etag.as_deref(), Some("\"abc\""));
+    assert!(cache.is_fresh(&entry));
+    // Other tokens may see other data.
+    assert!(cache.get(url, Some("hf_xxx")).is_none());
+
+    assert!(!MetadataCache::new(dir.clone(), Duration::ZERO, false).is_fresh(&entry));
+    assert!(MetadataCache::new(dir.clone(), DEFAULT_TTL, true).get(url, None).is_none());
+    std::fs::remove_dir_all(dir).unwrap();
+}
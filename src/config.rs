@@ -0,0 +1,93 @@
+use std::env;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::cache;
+
+/// Settings read from `config.json`, every key is optional.
+///
+/// ```json
+/// { "allowed_licenses": ["apache-2.0", "mit"], "metadata_ttl": 600 }
+/// ```
+#[derive(Debug)]
+pub struct Config {
+    /// Refuse to download repos whose license is not listed.
+    pub allowed_licenses: Option<Vec<String>>,
+    /// How long cached API responses are used before revalidating their ETag.
+    pub metadata_ttl: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config { allowed_licenses: None, metadata_ttl: cache::DEFAULT_TTL }
+    }
+}
+
+impl Config {
+    /// `$HFRS_CONFIG`, else `<config dir>/hfrs/config.json`.
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(path) = env::var_os("HFRS_CONFIG") {
+            return Some(PathBuf::from(path));
+        }
+        let base = if cfg!(target_os = "windows") {
+            env::var_os("APPDATA").map(PathBuf::from)
+        } else {
+            env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+        };
+        base.map(|b| b.join("hfrs").join("config.json"))
+    }
+
+    /// `<cache dir>/hfrs`, where API metadata is cached.
+    pub fn cache_dir() -> Option<PathBuf> {
+        let base = if cfg!(target_os = "windows") {
+            env::var_os("LOCALAPPDATA").map(PathBuf::from)
+        } else {
+            env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))
+        };
+        base.map(|b| b.join("hfrs"))
+    }
+
+    /// Load the config, a missing file gives the defaults.
+    pub fn load(path: Option<&Path>) -> Result<Config, Box<dyn Error>> {
+        let Some(path) = path.map(Path::to_path_buf).or_else(Config::default_path) else {
+            return Ok(Config::default());
+        };
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let text = std::fs::read_to_string(&path)?;
+        Config::parse(&text).map_err(|e| format!("Invalid config {}: {e}", path.display()).into())
+    }
+
+    fn parse(text: &str) -> Result<Config, Box<dyn Error>> {
+        let value: Value = serde_json::from_str(text)?;
+        let allowed_licenses = match &value["allowed_licenses"] {
+            Value::Null => None,
+            Value::Array(list) => Some(list.iter()
+                .map(|l| l.as_str().map(str::to_string).ok_or("`allowed_licenses` must be a list of strings"))
+                .collect::<Result<_, _>>()?),
+            _ => return Err("`allowed_licenses` must be a list of strings".into()),
+        };
+        let metadata_ttl = match &value["metadata_ttl"] {
+            Value::Null => cache::DEFAULT_TTL,
+            v => Duration::from_secs(v.as_u64().ok_or("`metadata_ttl` must be a number of seconds")?),
+        };
+        Ok(Config { allowed_licenses, metadata_ttl })
+    }
+}
+
+#[test]
+fn parse_config() {
+    let config = Config::parse(r#"{"allowed_licenses": ["apache-2.0", "mit"]}"#).unwrap();
+    assert_eq!(config.allowed_licenses, Some(vec!["apache-2.0".to_string(), "mit".to_string()]));
+    assert!(Config::parse("{}").unwrap().allowed_licenses.is_none());
+    assert_eq!(Config::parse(r#"{"metadata_ttl": 0}"#).unwrap().metadata_ttl, Duration::ZERO);
+    assert!(Config::parse(r#"{"allowed_licenses": "mit"}"#).is_err());
+}
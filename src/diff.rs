@@ -0,0 +1,229 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+use indicatif::BinaryBytes;
+use ring::digest::{digest, Context, SHA1_FOR_LEGACY_USE_ONLY, SHA256};
+use serde_json::Value;
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+
+use crate::api::{HubApi, RepoType};
+use crate::bundle::list_files;
+use crate::hex;
+
+const LFS_POINTER_PREFIX: &[u8] = b"version https://git-lfs.github.com/spec/v1";
+
+/// A file of the remote snapshot.
+struct RemoteFile {
+    size: u64,
+    /// git blob sha1 of regular files.
+    blob: Option<String>,
+    /// sha256 of LFS files.
+    lfs: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Change {
+    Added(u64),
+    Removed(u64),
+    Resized(u64, u64),
+    Modified,
+    /// Still the LFS pointer git checked out, the object was never downloaded.
+    NotDownloaded(u64),
+}
+
+/// Split `org/name@revision`, the revision defaults to `main`.
+fn parse_target(target: &str) -> (&str, &str) {
+    target.split_once('@').unwrap_or((target, "main"))
+}
+
+pub async fn hash_file(path: &Path, algorithm: &'static ring::digest::Algorithm, prefix: &[u8]) -> Result<String, Box<dyn Error>> {
+    let mut file = File::open(path).await?;
+    let mut ctx = Context::new(algorithm);
+    ctx.update(prefix);
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        ctx.update(&buf[..n]);
+    }
+    Ok(hex(ctx.finish().as_ref()))
+}
+
+fn is_symlink(path: &Path) -> bool {
+    std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink())
+}
+
+/// Target path of a symlink, which is what git stores as its blob.
+fn link_text(link: &Path) -> Result<String, Box<dyn Error>> {
+    Ok(std::fs::read_link(link)?.to_string_lossy().replace('\\', "/"))
+}
+
+/// Whether the symlink `link` points where the remote blob says.
+fn link_matches(link: &Path, file: &RemoteFile) -> Result<bool, Box<dyn Error>> {
+    let target = link_text(link)?;
+    let mut blob = format!("blob {}\0", target.len()).into_bytes();
+    blob.extend_from_slice(target.as_bytes());
+    Ok(file.lfs.is_none() && file.blob.as_deref() == Some(&hex(digest(&SHA1_FOR_LEGACY_USE_ONLY, &blob).as_ref())))
+}
+
+async fn is_lfs_pointer(path: &Path, size: u64) -> bool {
+    if size > 1024 {
+        return false;
+    }
+    tokio::fs::read(path).await.is_ok_and(|c| c.starts_with(LFS_POINTER_PREFIX))
+}
+
+fn remote_files(tree: &[Value]) -> BTreeMap<String, RemoteFile> {
+    tree.iter()
+        .filter(|e| e["type"].as_str() == Some("file"))
+        .filter_map(|e| Some((e["path"].as_str()?.to_string(), RemoteFile {
+            size: e["lfs"]["size"].as_u64().or(e["size"].as_u64())?,
+            blob: e["oid"].as_str().map(str::to_string),
+            lfs: e["lfs"]["oid"].as_str().map(str::to_string),
+        })))
+        .collect()
+}
+
+async fn compare(dir: &Path, remote: &BTreeMap<String, RemoteFile>, hash_lfs: bool) -> Result<Vec<(String, Change)>, Box<dyn Error>> {
+    let local = list_files(dir)?;
+    let mut changes = Vec::new();
+
+    for path in &local {
+        let full = dir.join(path);
+        // Symlinks are compared as git stores them, by their target path.
+        let link = is_symlink(&full);
+        let size = if link {
+            link_text(&full)?.len() as u64
+        } else {
+            std::fs::metadata(&full)?.len()
+        };
+        let Some(file) = remote.get(path) else {
+            changes.push((path.clone(), Change::Removed(size)));
+            continue;
+        };
+
+        if link {
+            if !link_matches(&full, file)? {
+                changes.push((path.clone(), Change::Modified));
+            }
+        } else if file.lfs.is_some() && size != file.size && is_lfs_pointer(&full, size).await {
+            changes.push((path.clone(), Change::NotDownloaded(file.size)));
+        } else if size != file.size {
+            changes.push((path.clone(), Change::Resized(size, file.size)));
+        } else if let Some(sha256) = &file.lfs {
+            if hash_lfs && hash_file(&full, &SHA256, b"").await? != *sha256 {
+                changes.push((path.clone(), Change::Modified));
+            }
+        } else if let Some(blob) = &file.blob {
+            let header = format!("blob {size}\0");
+            if hash_file(&full, &SHA1_FOR_LEGACY_USE_ONLY, header.as_bytes()).await? != *blob {
+                changes.push((path.clone(), Change::Modified));
+            }
+        }
+    }
+
+    for (path, file) in remote {
+        if local.binary_search(path).is_ok() {
+            continue;
+        }
+        // `list_files` skips symlinks to directories.
+        let full = dir.join(path);
+        if !is_symlink(&full) {
+            changes.push((path.clone(), Change::Added(file.size)));
+        } else if !link_matches(&full, file)? {
+            changes.push((path.clone(), Change::Modified));
+        }
+    }
+    changes.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(changes)
+}
+
+fn format_change(path: &str, change: &Change) -> String {
+    match change {
+        Change::Added(size) => format!("+ {path} ({})", BinaryBytes(*size)),
+        Change::Removed(size) => format!("- {path} ({}, only local)", BinaryBytes(*size)),
+        Change::Resized(local, remote) => format!("M {path} ({} -> {})", BinaryBytes(*local), BinaryBytes(*remote)),
+        Change::Modified => format!("M {path} (content differs)"),
+        Change::NotDownloaded(size) => format!("! {path} (LFS pointer only, {} to download)", BinaryBytes(*size)),
+    }
+}
+
+/// Print what differs between a local copy and a remote revision.
+pub async fn diff(api: &HubApi, dir: &Path, target: &str, repo_type: RepoType, hash_lfs: bool) -> Result<(), Box<dyn Error>> {
+    let (repo_id, revision) = parse_target(target);
+    println!("Comparing {} with {repo_id}@{revision}...", dir.display());
+    let tree = api.list_tree(repo_type, repo_id, revision, "", true).await?;
+    let remote = remote_files(&tree);
+    let changes = compare(dir, &remote, hash_lfs).await?;
+
+    let mut download = 0;
+    for (path, change) in &changes {
+        println!("{}", format_change(path, change));
+        if let Change::Added(size) | Change::Resized(_, size) | Change::NotDownloaded(size) = change {
+            download += size;
+        }
+    }
+    if changes.is_empty() {
+        println!("Up to date{}.", if hash_lfs { "" } else { " (LFS files compared by size, use --hash to check content)" });
+    } else {
+        println!("{} change(s), about {} to download.", changes.len(), BinaryBytes(download));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn diff_local_against_remote() {
+    let dir = std::env::temp_dir().join(format!("hfrs_diff_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join(".git")).unwrap();
+    std::fs::write(dir.join("config.json"), "{}\n").unwrap();
+    std::fs::write(dir.join("README.md"), "old\n").unwrap();
+    std::fs::write(dir.join("model.safetensors"), "version https://git-lfs.github.com/spec/v1\noid sha256:abc\nsize 4096\n").unwrap();
+    std::fs::write(dir.join("extra.txt"), "x").unwrap();
+    std::fs::write(dir.join("tokenizer.json.incomplete"), "{").unwrap();
+
+    // `git hash-object` of "{}\n" is 0967ef424bce6791893e9a57bb952f80fd536e93.
+    let tree = serde_json::json!([
+        {"type": "file", "path": "config.json", "size": 3, "oid": "0967ef424bce6791893e9a57bb952f80fd536e93"},
+        {"type": "file", "path": "README.md", "size": 4, "oid": "0000000000000000000000000000000000000000"},
+        {"type": "file", "path": "model.safetensors", "size": 135, "oid": "x", "lfs": {"oid": "abc", "size": 4096}},
+        {"type": "file", "path": "tokenizer.json", "size": 10, "oid": "y"},
+        {"type": "directory", "path": "vae"}
+    ]);
+    let changes = compare(&dir, &remote_files(tree.as_array().unwrap()), false).await.unwrap();
+    assert_eq!(changes, vec![
+        ("README.md".to_string(), Change::Modified),
+        ("extra.txt".to_string(), Change::Removed(1)),
+        ("model.safetensors".to_string(), Change::NotDownloaded(4096)),
+        ("tokenizer.json".to_string(), Change::Added(10)),
+    ]);
+    assert_eq!(parse_target("org/name@refs/pr/1"), ("org/name", "refs/pr/1"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn diff_symlinks() {
+    let dir = std::env::temp_dir().join(format!("hfrs_diff_links_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("config.json"), "{}\n").unwrap();
+    std::fs::write(dir.join("sub/a.json"), "{}\n").unwrap();
+    std::os::unix::fs::symlink("config.json", dir.join("link.json")).unwrap();
+    std::os::unix::fs::symlink("sub", dir.join("sublink")).unwrap();
+    std::os::unix::fs::symlink("sub/a.json", dir.join("moved.json")).unwrap();
+
+    // Blob sha1 of the link texts "config.json" and "sub".
+    let tree = serde_json::json!([
+        {"type": "file", "path": "config.json", "size": 3, "oid": "0967ef424bce6791893e9a57bb952f80fd536e93"},
+        {"type": "file", "path": "sub/a.json", "size": 3, "oid": "0967ef424bce6791893e9a57bb952f80fd536e93"},
+        {"type": "file", "path": "link.json", "size": 11, "oid": "0cffcb348ff9cec9cd41492a9e5c5a41d86ce96e"},
+        {"type": "file", "path": "sublink", "size": 3, "oid": "3de0f365ba57c94daac626bf53a7da269b65f57c"},
+        {"type": "file", "path": "moved.json", "size": 11, "oid": "0cffcb348ff9cec9cd41492a9e5c5a41d86ce96e"}
+    ]);
+    let changes = compare(&dir, &remote_files(tree.as_array().unwrap()), false).await.unwrap();
+    assert_eq!(changes, vec![("moved.json".to_string(), Change::Modified)]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
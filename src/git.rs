@@ -0,0 +1,585 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::Url;
+use serde_json::Value;
+use tokio::process::Command;
+
+use crate::http;
+
+/// Lock files git leaves behind when it is killed mid operation.
+const LOCK_FILES: [&str; 5] = ["index.lock", "HEAD.lock", "config.lock", "shallow.lock", "packed-refs.lock"];
+
+/// Lock files younger than this may belong to a git that is still running.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(3600);
+
+/// What we found in `<dir>/.git` before syncing.
+#[derive(Debug, PartialEq)]
+pub enum CloneState {
+    Missing,
+    Healthy,
+    /// A clone of a repo without any commit yet.
+    Empty,
+    /// A clone that never got to a checked out commit, with the reason.
+    Broken(String),
+}
+
+/// `git` with LFS smudging disabled, lfs objects are fetched through the proxy instead.
+/// `core.quotepath=false` keeps non-ASCII paths readable instead of octal escaped.
+pub fn git(dir: Option<&Path>) -> Command {
+    let mut cmd = Command::new("git");
+    cmd.env("GIT_LFS_SKIP_SMUDGE", "1")
+        .args(["-c", "core.quotepath=false"]);
+    // `--user-agent` and `--header`, through the environment so header values stay out of argv.
+    let config = http::git_config();
+    if !config.is_empty() {
+        let base = std::env::var("GIT_CONFIG_COUNT").ok().and_then(|c| c.parse().ok()).unwrap_or(0usize);
+        for (i, (key, value)) in config.iter().enumerate() {
+            cmd.env(format!("GIT_CONFIG_KEY_{}", base + i), key)
+                .env(format!("GIT_CONFIG_VALUE_{}", base + i), value);
+        }
+        cmd.env("GIT_CONFIG_COUNT", (base + config.len()).to_string());
+    }
+    if let Some(dir) = dir {
+        cmd.current_dir(dir);
+        // Never fall back to a repository above `dir`.
+        if let Some(parent) = dir.parent() {
+            cmd.env("GIT_CEILING_DIRECTORIES", parent);
+        }
+    }
+    cmd
+}
+
+/// Run a git query in `dir`, failing if git cannot be started at all.
+async fn query(dir: &Path, args: &[&str]) -> Result<std::process::Output, Box<dyn Error>> {
+    Ok(git(Some(dir)).args(args).output().await.map_err(|e| format!("Cant run git: {e}"))?)
+}
+
+/// Only a clone git itself reports as having no commit is `Broken`, anything
+/// git refuses to work with (ownership, corruption...) is an error for the user to look at.
+pub async fn inspect_clone(dir: &Path) -> Result<CloneState, Box<dyn Error>> {
+    if !dir.join(".git").exists() {
+        return Ok(CloneState::Missing);
+    }
+    let out = query(dir, &["rev-parse", "--git-dir"]).await?;
+    if !out.status.success() {
+        return Err(format!(
+            "git cannot use the clone in {}: {}",
+            dir.display(),
+            String::from_utf8_lossy(&out.stderr).trim()
+        ).into());
+    }
+    if query(dir, &["rev-parse", "--verify", "--quiet", "HEAD^{commit}"]).await?.status.success() {
+        return Ok(CloneState::Healthy);
+    }
+
+    // Unborn HEAD: either the clone was interrupted or the repo has no commit yet.
+    let out = query(dir, &["ls-remote", "--heads", "origin"]).await?;
+    if !out.status.success() {
+        return Err(format!(
+            "The clone in {} has no commit and its origin cannot be listed: {}",
+            dir.display(),
+            String::from_utf8_lossy(&out.stderr).trim()
+        ).into());
+    }
+    if out.stdout.is_empty() {
+        return Ok(CloneState::Empty);
+    }
+    Ok(CloneState::Broken("HEAD does not point to a commit, the clone never finished".to_string()))
+}
+
+/// Remove lock files left by an interrupted git, they make every later command fail.
+/// Recent locks may belong to a git still running (e.g. an overlapping cron run),
+/// those are reported instead of removed.
+pub fn remove_stale_locks(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let git_dir = dir.join(".git");
+    let mut removed = Vec::new();
+    for lock in LOCK_FILES.iter().map(|l| git_dir.join(l)) {
+        let Ok(meta) = std::fs::metadata(&lock) else {
+            continue;
+        };
+        let age = meta.modified()?.elapsed().unwrap_or_default();
+        if age < STALE_LOCK_AGE {
+            return Err(format!(
+                "{} is {}s old, another git may still be working in {}. Wait for it, or remove the lock if it is not running.",
+                lock.display(),
+                age.as_secs(),
+                dir.display()
+            ).into());
+        }
+        std::fs::remove_file(&lock)?;
+        removed.push(lock);
+    }
+    Ok(removed)
+}
+
+async fn run(mut cmd: Command, what: &str) -> Result<(), Box<dyn Error>> {
+    let status = cmd
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .await
+        .map_err(|e| format!("Cant run `{what}`: {e}"))?;
+    if !status.success() {
+        return Err(format!("`{what}` failed with {status}").into());
+    }
+    Ok(())
+}
+
+/// Files tracked by git but missing on disk, e.g. after a failed checkout.
+async fn deleted_files(dir: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let out = git(Some(dir)).args(["ls-files", "-z", "--deleted"]).output().await?;
+    Ok(String::from_utf8(out.stdout)?
+        .split('\0')
+        .filter(|f| !f.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Check out tracked files missing on disk, e.g. after a failed checkout.
+async fn restore_deleted(dir: &Path) -> Result<(), Box<dyn Error>> {
+    let missing = deleted_files(dir).await?;
+    if !missing.is_empty() {
+        println!("Restoring {} file(s) missing after an unfinished checkout...", missing.len());
+        let mut cmd = git(Some(dir));
+        cmd.args(["checkout", "--"]).args(&missing);
+        run(cmd, "git checkout").await?;
+    }
+    Ok(())
+}
+
+/// Refuse to update a clone of another repo that happens to live in `dir`.
+/// Only the path is compared, the same repo may have been cloned from another mirror.
+async fn check_origin(url: &Url, dir: &Path) -> Result<(), Box<dyn Error>> {
+    let out = query(dir, &["remote", "get-url", "origin"]).await?;
+    let origin = String::from_utf8_lossy(&out.stdout);
+    let same_repo = Url::parse(origin.trim())
+        .is_ok_and(|o| o.path().trim_end_matches('/') == url.path().trim_end_matches('/'));
+    if !same_repo {
+        return Err(format!(
+            "{} is a clone of `{}`, not {url}; choose another --local-dir",
+            dir.display(),
+            origin.trim()
+        ).into());
+    }
+    Ok(())
+}
+
+/// Replace the `.git` of an interrupted clone with a fresh one, cloned next to
+/// `dir` so files already downloaded into it are kept.
+async fn reclone(url: &Url, dir: &Path) -> Result<(), Box<dyn Error>> {
+    let name = dir.file_name().ok_or("Clone directory has no name")?.to_string_lossy();
+    let tmp = dir.with_file_name(format!(".{name}.hfrs-clone"));
+    if tmp.exists() {
+        std::fs::remove_dir_all(&tmp)?;
+    }
+    let mut cmd = git(None);
+    cmd.args(["clone", "--no-checkout"]).arg(url.to_string()).arg(&tmp);
+    run(cmd, "git clone").await?;
+
+    std::fs::remove_dir_all(dir.join(".git"))?;
+    std::fs::rename(tmp.join(".git"), dir.join(".git"))?;
+    std::fs::remove_dir_all(&tmp)?;
+    let mut cmd = git(Some(dir));
+    cmd.args(["reset", "-q"]);
+    run(cmd, "git reset").await
+}
+
+/// Clone `url` into `dir`, or bring an existing clone up to date, repairing
+/// what an interrupted previous run left behind.
+///
+/// Without `checkout` a fresh clone only gets its index set to HEAD and the
+/// working tree is left empty; returns whether that happened.
+pub async fn sync(url: &Url, dir: &Path, checkout: bool) -> Result<bool, Box<dyn Error>> {
+    match inspect_clone(dir).await? {
+        CloneState::Missing => {}
+        CloneState::Empty => {
+            check_origin(url, dir).await?;
+            println!("{} is a clone of an empty repo, nothing to update.", dir.display());
+            return Ok(false);
+        }
+        CloneState::Broken(reason) => {
+            println!("Found an interrupted clone in {} ({reason}), cloning it again...", dir.display());
+            reclone(url, dir).await?;
+            if !checkout {
+                return Ok(true);
+            }
+            restore_deleted(dir).await?;
+            return Ok(false);
+        }
+        CloneState::Healthy => {
+            check_origin(url, dir).await?;
+            for lock in remove_stale_locks(dir)? {
+                println!("Removed stale lock {}", lock.display());
+            }
+            restore_deleted(dir).await?;
+
+            println!("Executing `git pull`...");
+            let mut cmd = git(Some(dir));
+            cmd.arg("pull");
+            run(cmd, "git pull").await.map_err(|e| format!(
+                "{e}. The clone in {} could not be updated, run `git status` there or delete it to download again.",
+                dir.display()
+            ))?;
+            return Ok(false);
+        }
+    }
+
+    println!("Executing `git clone {}`...", url);
+    let mut cmd = git(None);
+    cmd.arg("clone");
+    if !checkout {
+        cmd.arg("--no-checkout");
+    }
+    cmd.arg(url.to_string()).arg(dir);
+    run(cmd, "git clone").await?;
+
+    if !checkout {
+        let mut cmd = git(Some(dir));
+        cmd.args(["reset", "-q"]);
+        run(cmd, "git reset").await?;
+    }
+    Ok(!checkout)
+}
+
+/// Every file tracked in the index that is neither a symlink nor a submodule.
+pub async fn regular_files(dir: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let out = git(Some(dir)).args(["ls-files", "-s", "-z"]).output().await?;
+    Ok(String::from_utf8(out.stdout)?
+        .split('\0')
+        .filter_map(|entry| entry.split_once('\t'))
+        .filter(|(meta, _)| meta.starts_with("100644 ") || meta.starts_with("100755 "))
+        .map(|(_, path)| path.to_string())
+        .collect())
+}
+
+/// Time of the last commit touching each path in the history of HEAD.
+async fn commit_dates(dir: &Path) -> Result<HashMap<String, SystemTime>, Box<dyn Error>> {
+    // A NUL starts each commit line, it cannot appear in a path.
+    let out = git(Some(dir)).args(["log", "--format=%x00%ct", "--name-only", "--no-renames", "HEAD"]).output().await?;
+    let mut dates = HashMap::new();
+    let mut current = None;
+    for line in String::from_utf8_lossy(&out.stdout).lines() {
+        if let Some(time) = line.strip_prefix('\0') {
+            current = time.parse().ok().map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+        } else if let (false, Some(time)) = (line.is_empty(), current) {
+            dates.entry(unquote_path(line)).or_insert(time);
+        }
+    }
+    Ok(dates)
+}
+
+/// Give `files` checked out by git the date of their last commit, so their
+/// mtime is the same in every clone like the Last-Modified of downloaded files.
+pub async fn stamp_commit_dates(dir: &Path, files: &[String]) -> Result<(), Box<dyn Error>> {
+    let dates = commit_dates(dir).await?;
+    for file in files {
+        if let Some(time) = dates.get(file) {
+            if let Ok(f) = std::fs::File::options().write(true).open(dir.join(file)) {
+                f.set_modified(*time).ok();
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Refresh the index stat info after files were written behind git's back.
+pub async fn refresh_index(dir: &Path) -> Result<(), Box<dyn Error>> {
+    let mut cmd = git(Some(dir));
+    cmd.args(["update-index", "-q", "--refresh"]);
+    // Exits non zero while some files are still missing, which failed downloads may cause.
+    run(cmd, "git update-index").await.ok();
+    Ok(())
+}
+
+/// Undo git's C style quoting, `"\344\270\255.txt"` -> `中.txt`. Unquoted paths are returned as is.
+pub fn unquote_path(path: &str) -> String {
+    let Some(inner) = path.strip_prefix('"').and_then(|p| p.strip_suffix('"')) else {
+        return path.to_string();
+    };
+    let mut bytes = Vec::with_capacity(inner.len());
+    let mut chars = inner.bytes().peekable();
+    while let Some(b) = chars.next() {
+        if b != b'\\' {
+            bytes.push(b);
+            continue;
+        }
+        match chars.next() {
+            Some(d @ b'0'..=b'7') => {
+                let mut value = (d - b'0') as u32;
+                for _ in 0..2 {
+                    match chars.peek() {
+                        Some(d @ b'0'..=b'7') => {
+                            value = value * 8 + (d - b'0') as u32;
+                            chars.next();
+                        }
+                        _ => break,
+                    }
+                }
+                bytes.push(value as u8);
+            }
+            Some(b'n') => bytes.push(b'\n'),
+            Some(b't') => bytes.push(b'\t'),
+            Some(b'r') => bytes.push(b'\r'),
+            Some(b'a') => bytes.push(0x07),
+            Some(b'b') => bytes.push(0x08),
+            Some(b'f') => bytes.push(0x0c),
+            Some(b'v') => bytes.push(0x0b),
+            Some(other) => bytes.push(other),
+            None => bytes.push(b'\\'),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// `(oid, file name)` of a `git lfs ls-files -l` line, `<oid> - <name>` or `<oid> * <name>`.
+pub fn parse_lfs_line(line: &str) -> Option<(String, String)> {
+    let (oid, rest) = line.split_once(' ')?;
+    let name = rest.strip_prefix("- ").or_else(|| rest.strip_prefix("* "))?;
+    Some((oid.to_string(), unquote_path(name.trim_end())))
+}
+
+/// Symlinks tracked by git, as `(path, target)` with the target as stored in the repo.
+pub async fn symlink_entries(dir: &Path) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let out = git(Some(dir)).args(["ls-files", "-s", "-z"]).output().await?;
+    let mut links = Vec::new();
+    for entry in String::from_utf8(out.stdout)?.split('\0').filter(|e| !e.is_empty()) {
+        // `<mode> <object> <stage>\t<path>`
+        let Some((meta, path)) = entry.split_once('\t') else {
+            continue;
+        };
+        let mut meta = meta.split(' ');
+        if meta.next() != Some("120000") {
+            continue;
+        }
+        let Some(object) = meta.next() else {
+            continue;
+        };
+        let target = git(Some(dir)).args(["cat-file", "blob", object]).output().await?;
+        links.push((path.to_string(), String::from_utf8(target.stdout)?));
+    }
+    Ok(links)
+}
+
+/// Where `target` of the link at `path` points, if it stays inside the repo.
+pub fn resolve_link(path: &str, target: &str) -> Option<String> {
+    if target.starts_with('/') || target.contains(':') {
+        return None;
+    }
+    let mut parts: Vec<&str> = path.split('/').collect();
+    parts.pop();
+    for part in target.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+#[cfg(unix)]
+fn make_symlink(target: &str, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn make_symlink(target: &str, link: &Path) -> std::io::Result<()> {
+    let resolved = link.parent().map(|p| p.join(target));
+    if resolved.is_some_and(|p| p.is_dir()) {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    }
+}
+
+/// Turn symlinks git wrote as plain text files (`core.symlinks=false`) back into links,
+/// or with `dereference` replace every link with a copy of the file it points to.
+pub async fn fix_symlinks(dir: &Path, dereference: bool) -> Result<(), Box<dyn Error>> {
+    restore_links(dir, symlink_entries(dir).await?, dereference)
+}
+
+/// Symlinks among `files` fetched over http, where git cannot tell the file mode.
+///
+/// The resolve endpoint serves a link as its target text, so a small file whose
+/// whole content is a relative path to another file of `tree`, with a different
+/// blob, is taken as a link. Directories are never targets: a text file naming
+/// one (e.g. a checkpoint's `latest`) is data.
+pub fn guess_links(dir: &Path, files: &[String], tree: &[Value]) -> Vec<(String, String)> {
+    let blobs: HashMap<&str, &str> = tree.iter()
+        .filter(|e| e["type"].as_str() == Some("file"))
+        .filter_map(|e| Some((e["path"].as_str()?, e["oid"].as_str()?)))
+        .collect();
+    files.iter()
+        .filter_map(|path| {
+            let full = dir.join(path);
+            if std::fs::metadata(&full).ok()?.len() > 1024 {
+                return None;
+            }
+            let target = String::from_utf8(std::fs::read(&full).ok()?).ok()?;
+            if target.is_empty() || target.contains(['\n', '\0']) {
+                return None;
+            }
+            let resolved = resolve_link(path, &target)?;
+            let target_blob = blobs.get(resolved.as_str())?;
+            (blobs.get(path.as_str()) != Some(target_blob)).then(|| (path.clone(), target))
+        })
+        .collect()
+}
+
+/// Write `links` as symlinks, or with `dereference` as copies of their targets.
+pub fn restore_links(dir: &Path, links: Vec<(String, String)>, dereference: bool) -> Result<(), Box<dyn Error>> {
+    for (path, target) in links {
+        let link = dir.join(&path);
+        let is_link = std::fs::symlink_metadata(&link).is_ok_and(|m| m.file_type().is_symlink());
+
+        if dereference {
+            let Some(resolved) = resolve_link(&path, &target).map(|r| dir.join(r)) else {
+                println!("Keep symlink {path} -> {target}, it points outside the repo");
+                continue;
+            };
+            if !resolved.is_file() {
+                println!("Keep symlink {path} -> {target}, the target is not a file");
+                continue;
+            }
+            std::fs::remove_file(&link)?;
+            std::fs::copy(&resolved, &link)?;
+            println!("Dereferenced {path} -> {target}");
+        } else if !is_link {
+            std::fs::remove_file(&link).ok();
+            match make_symlink(&target, &link) {
+                Ok(()) => println!("Recreated symlink {path} -> {target}"),
+                Err(e) => {
+                    std::fs::write(&link, &target)?;
+                    println!("Cant create symlink {path} -> {target} ({e}), use --dereference to copy the target instead");
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn detect_broken_clone() {
+    let root = std::env::temp_dir().join(format!("hfrs_git_{}", std::process::id()));
+    let origin = root.join("origin");
+    std::fs::create_dir_all(&origin).unwrap();
+    assert!(git(Some(&origin)).args(["init", "-q"]).status().await.unwrap().success());
+
+    // A clone of an empty repo is unborn too, but not broken.
+    let dir = root.join("clone");
+    let url = Url::from_directory_path(&origin).unwrap();
+    assert!(git(None).arg("clone").arg(url.as_str()).arg(&dir).output().await.unwrap().status.success());
+    assert_eq!(inspect_clone(&dir).await.unwrap(), CloneState::Empty);
+
+    std::fs::write(origin.join("a.txt"), "a").unwrap();
+    for args in [&["add", "."][..], &["-c", "user.name=t", "-c", "user.email=t@t", "commit", "-qm", "init"]] {
+        assert!(git(Some(&origin)).args(args).status().await.unwrap().success());
+    }
+    std::fs::write(dir.join("kept.bin"), "downloaded").unwrap();
+    assert!(matches!(inspect_clone(&dir).await.unwrap(), CloneState::Broken(_)));
+    sync(&url, &dir, true).await.unwrap();
+    assert_eq!(inspect_clone(&dir).await.unwrap(), CloneState::Healthy);
+    assert!(dir.join("a.txt").exists() && dir.join("kept.bin").exists());
+
+    // A lock of a possibly running git is kept, an old one is removed.
+    let lock = dir.join(".git/index.lock");
+    std::fs::write(&lock, "").unwrap();
+    assert!(remove_stale_locks(&dir).is_err());
+    std::fs::File::options().write(true).open(&lock).unwrap()
+        .set_modified(std::time::SystemTime::now() - STALE_LOCK_AGE * 2).unwrap();
+    assert_eq!(remove_stale_locks(&dir).unwrap(), vec![lock]);
+
+    // A .git git refuses is an error, never a reason to delete anything.
+    std::fs::remove_dir_all(dir.join(".git")).unwrap();
+    std::fs::create_dir(dir.join(".git")).unwrap();
+    assert!(inspect_clone(&dir).await.is_err());
+
+    std::fs::remove_dir_all(&root).unwrap();
+    assert_eq!(inspect_clone(&dir).await.unwrap(), CloneState::Missing);
+}
+
+#[tokio::test]
+async fn clone_without_checkout() {
+    let root = std::env::temp_dir().join(format!("hfrs_nocheckout_{}", std::process::id()));
+    let origin = root.join("origin");
+    std::fs::create_dir_all(origin.join("sub")).unwrap();
+    std::fs::write(origin.join("sub/a.json"), "{}").unwrap();
+    for args in [&["init", "-q"][..], &["add", "."], &["-c", "user.name=t", "-c", "user.email=t@t", "commit", "-qm", "init"]] {
+        assert!(git(Some(&origin)).args(args).status().await.unwrap().success());
+    }
+
+    let url = Url::from_directory_path(&origin).unwrap();
+    let dir = root.join("clone");
+    assert!(sync(&url, &dir, false).await.unwrap());
+    assert!(!dir.join("sub/a.json").exists());
+    assert_eq!(regular_files(&dir).await.unwrap(), vec!["sub/a.json".to_string()]);
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[tokio::test]
+async fn commit_dates_as_mtime() {
+    let dir = std::env::temp_dir().join(format!("hfrs_dates_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    assert!(git(Some(&dir)).args(["init", "-q"]).status().await.unwrap().success());
+    for (file, date) in [("a.txt", "1700000000 +0000"), ("b.txt", "1710000000 +0000")] {
+        std::fs::write(dir.join(file), file).unwrap();
+        for args in [&["add", "."][..], &["-c", "user.name=t", "-c", "user.email=t@t", "commit", "-qm", file]] {
+            assert!(git(Some(&dir)).args(args).env("GIT_COMMITTER_DATE", date).status().await.unwrap().success());
+        }
+    }
+
+    stamp_commit_dates(&dir, &["a.txt".to_string(), "b.txt".to_string()]).await.unwrap();
+    let mtime = |f: &str| std::fs::metadata(dir.join(f)).unwrap().modified().unwrap();
+    assert_eq!(mtime("a.txt"), UNIX_EPOCH + Duration::from_secs(1700000000));
+    assert_eq!(mtime("b.txt"), UNIX_EPOCH + Duration::from_secs(1710000000));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn non_ascii_paths() {
+    assert_eq!(unquote_path(r#""\344\270\255\346\226\207/\346\225\260\346\215\256.json""#), "中文/数据.json");
+    assert_eq!(unquote_path(r#""a\"b\\c\td""#), "a\"b\\c\td");
+    assert_eq!(unquote_path("plain/name.bin"), "plain/name.bin");
+    assert_eq!(parse_lfs_line("a1b2c3d4e5 - データ/モデル.safetensors"), Some(("a1b2c3d4e5".to_string(), "データ/モデル.safetensors".to_string())));
+    assert_eq!(parse_lfs_line(r#"a1b2c3d4e5 * "\344\270\255-1.bin""#).map(|(_, name)| name).as_deref(), Some("中-1.bin"));
+    assert_eq!(parse_lfs_line("garbage"), None);
+}
+
+#[test]
+fn symlink_targets() {
+    assert_eq!(resolve_link("a/b/link", "../c/file").as_deref(), Some("a/c/file"));
+    assert_eq!(resolve_link("link", "./file").as_deref(), Some("file"));
+    assert_eq!(resolve_link("link", "../outside"), None);
+    assert_eq!(resolve_link("link", "/etc/passwd"), None);
+
+    let dir = std::env::temp_dir().join(format!("hfrs_links_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("a")).unwrap();
+    std::fs::write(dir.join("a/latest"), "../v2/model.bin").unwrap();
+    std::fs::write(dir.join("a/notes.txt"), "../v2/model.bin\n").unwrap();
+    std::fs::write(dir.join("name.txt"), "missing.bin").unwrap();
+    // A DeepSpeed `latest` naming a checkpoint directory, and a file holding the same text as its "target".
+    std::fs::write(dir.join("latest"), "global_step1000").unwrap();
+    std::fs::write(dir.join("copy.txt"), "name.txt").unwrap();
+    let files = ["a/latest", "a/notes.txt", "name.txt", "latest", "copy.txt"].map(str::to_string);
+    let tree = serde_json::json!([
+        {"type": "file", "path": "a/latest", "oid": "1"},
+        {"type": "file", "path": "v2/model.bin", "oid": "2"},
+        {"type": "directory", "path": "v2", "oid": "3"},
+        {"type": "directory", "path": "global_step1000", "oid": "4"},
+        {"type": "file", "path": "latest", "oid": "5"},
+        {"type": "file", "path": "name.txt", "oid": "6"},
+        {"type": "file", "path": "copy.txt", "oid": "6"}
+    ]);
+    assert_eq!(
+        guess_links(&dir, &files, tree.as_array().unwrap()),
+        vec![("a/latest".to_string(), "../v2/model.bin".to_string())]
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,183 @@
+use std::error::Error;
+use std::sync::OnceLock;
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, LOCATION, USER_AGENT};
+use reqwest::redirect::Policy;
+use reqwest::{Client, ClientBuilder, RequestBuilder, Response};
+
+/// Headers sent with every request: user agent and `--header` values.
+static DEFAULT_HEADERS: OnceLock<HeaderMap> = OnceLock::new();
+/// The same settings for git, as `(key, value)` config pairs.
+static GIT_CONFIG: OnceLock<Vec<(String, String)>> = OnceLock::new();
+static CLIENT: OnceLock<Client> = OnceLock::new();
+/// Follows redirects to other hosts, without the secret headers.
+static PUBLIC_CLIENT: OnceLock<Client> = OnceLock::new();
+
+const DEFAULT_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Parse a `--header` value, `Name: value`.
+pub fn parse_header(s: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, value) = s.split_once(':').ok_or(format!("`{s}` is not in `Name: value` form"))?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|e| format!("Invalid header name: {e}"))?;
+    let mut value = HeaderValue::from_str(value.trim()).map_err(|e| format!("Invalid header value: {e}"))?;
+    value.set_sensitive(is_secret(&name));
+    Ok((name, value))
+}
+
+/// Headers whose value should never end up in logs.
+fn is_secret(name: &HeaderName) -> bool {
+    let name = name.as_str();
+    ["authorization", "cookie", "token", "secret", "key", "password", "session"]
+        .iter()
+        .any(|s| name.contains(s))
+}
+
+/// `Name: value` for logs, with secret values masked.
+pub fn display_header(name: &HeaderName, value: &HeaderValue) -> String {
+    if is_secret(name) || value.is_sensitive() {
+        format!("{name}: ***")
+    } else {
+        format!("{name}: {}", value.to_str().unwrap_or("<binary>"))
+    }
+}
+
+/// Set the headers of every client, must run before the first request.
+pub fn configure(user_agent: Option<&str>, headers: &[(HeaderName, HeaderValue)]) -> Result<(), Box<dyn Error>> {
+    let mut map = HeaderMap::new();
+    let mut git_config = Vec::new();
+    map.insert(USER_AGENT, HeaderValue::from_str(user_agent.unwrap_or(DEFAULT_USER_AGENT))?);
+    if let Some(user_agent) = user_agent {
+        git_config.push(("http.userAgent".to_string(), user_agent.to_string()));
+    }
+    for (name, value) in headers {
+        println!("Sending header {}", display_header(name, value));
+        map.append(name.clone(), value.clone());
+        git_config.push(("http.extraHeader".to_string(), format!("{name}: {}", value.to_str()?)));
+    }
+    DEFAULT_HEADERS.set(map).map_err(|_| "http headers configured twice")?;
+    GIT_CONFIG.set(git_config).map_err(|_| "http headers configured twice")?;
+    Ok(())
+}
+
+/// `http.userAgent` and `http.extraHeader` entries for git.
+pub fn git_config() -> &'static [(String, String)] {
+    GIT_CONFIG.get().map_or(&[], Vec::as_slice)
+}
+
+fn default_headers() -> HeaderMap {
+    DEFAULT_HEADERS.get().cloned().unwrap_or_else(|| {
+        let mut map = HeaderMap::new();
+        map.insert(USER_AGENT, HeaderValue::from_static(DEFAULT_USER_AGENT));
+        map
+    })
+}
+
+/// With `keep_origin`, stop at a redirect to another origin instead of following
+/// it, `send` then follows it without the secret headers.
+fn redirect_policy(keep_origin: bool) -> Policy {
+    Policy::custom(move |attempt| {
+        if attempt.previous().len() > 10 {
+            attempt.error("too many redirects")
+        } else if keep_origin && attempt.previous().first().is_some_and(|first| first.origin() != attempt.url().origin()) {
+            attempt.stop()
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+/// A client builder with the configured headers, for clients needing more (e.g. auth).
+/// Requests of these clients must go through `send`.
+pub fn client_builder() -> ClientBuilder {
+    let headers = default_headers();
+    let has_secrets = headers.iter().any(|(name, value)| is_secret(name) || value.is_sensitive());
+    Client::builder().default_headers(headers).redirect(redirect_policy(has_secrets))
+}
+
+/// The client shared by downloads and url checks.
+pub fn client() -> &'static Client {
+    CLIENT.get_or_init(|| client_builder().build().expect("Cant build http client"))
+}
+
+/// A client without the secret headers, for presigned urls of other hosts.
+pub fn public_client() -> &'static Client {
+    PUBLIC_CLIENT.get_or_init(|| {
+        let mut headers = default_headers();
+        let secrets: Vec<_> = headers.keys().filter(|name| is_secret(name)).cloned().collect();
+        for name in secrets {
+            headers.remove(name);
+        }
+        Client::builder().default_headers(headers).build().expect("Cant build http client")
+    })
+}
+
+/// Send `req`; a redirect to another origin is followed without the secret
+/// headers, which would otherwise leak to e.g. the CDN behind `/resolve/`.
+pub async fn send(req: RequestBuilder) -> Result<Response, reqwest::Error> {
+    send_via(req, public_client()).await
+}
+
+async fn send_via(req: RequestBuilder, public: &Client) -> Result<Response, reqwest::Error> {
+    let (client, req) = req.build_split();
+    let req = req?;
+    let retry = req.try_clone();
+    let resp = client.execute(req).await?;
+    let location = resp.status()
+        .is_redirection()
+        .then(|| resp.headers().get(LOCATION))
+        .flatten()
+        .and_then(|l| l.to_str().ok())
+        .and_then(|l| resp.url().join(l).ok());
+    match (location, retry) {
+        (Some(location), Some(mut retry)) => {
+            *retry.url_mut() = location;
+            public.execute(retry).await
+        }
+        _ => Ok(resp),
+    }
+}
+
+#[test]
+fn headers_are_masked() {
+    let (name, value) = parse_header("X-Api-Key: s3cr3t").unwrap();
+    assert_eq!(display_header(&name, &value), "x-api-key: ***");
+    let (name, value) = parse_header("X-Mirror-Region:  eu ").unwrap();
+    assert_eq!(display_header(&name, &value), "x-mirror-region: eu");
+    assert!(parse_header("no colon").is_err());
+}
+
+#[tokio::test]
+async fn secrets_stay_on_origin() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    // Answers one request with `respond(request head)`.
+    fn serve(respond: impl FnOnce(String) -> String + Send + 'static) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut head = String::new();
+            let mut reader = BufReader::new(&stream);
+            while reader.read_line(&mut head).unwrap() > 2 {}
+            (&stream).write_all(respond(head.to_lowercase()).as_bytes()).unwrap();
+        });
+        port
+    }
+    let cdn = serve(|head| {
+        let body = if head.contains("x-api-key") { "leaked" } else { "clean" };
+        format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}", body.len())
+    });
+    let hub = serve(move |_| {
+        format!("HTTP/1.1 302 Found\r\nlocation: http://127.0.0.1:{cdn}/blob\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+    });
+
+    let (name, value) = parse_header("X-Api-Key: s3cr3t").unwrap();
+    let client = Client::builder()
+        .default_headers(HeaderMap::from_iter([(name, value)]))
+        .redirect(redirect_policy(true))
+        .build()
+        .unwrap();
+    let resp = send_via(client.get(format!("http://127.0.0.1:{hub}/resolve")), &Client::new()).await.unwrap();
+    assert_eq!(resp.text().await.unwrap(), "clean");
+}
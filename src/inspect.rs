@@ -0,0 +1,260 @@
+use std::error::Error;
+
+use futures_util::{stream, StreamExt};
+use indicatif::BinaryBytes;
+use serde_json::Value;
+
+use crate::api::{HubApi, RepoType};
+
+/// Repo info requests in flight while listing trending repos.
+const INFO_CONCURRENCY: usize = 8;
+
+pub async fn show_refs(api: &HubApi, repo_type: RepoType, repo_id: &str, include_prs: bool) -> Result<(), Box<dyn Error>> {
+    let refs = api.list_refs(repo_type, repo_id, include_prs).await?;
+    for (key, title) in [
+        ("branches", "Branches"),
+        ("tags", "Tags"),
+        ("converts", "Converts"),
+        ("pullRequests", "Pull requests"),
+    ] {
+        let Some(entries) = refs[key].as_array() else {
+            continue;
+        };
+        if entries.is_empty() {
+            continue;
+        }
+        println!("{title}:");
+        for line in format_refs(entries) {
+            println!("  {line}");
+        }
+    }
+    Ok(())
+}
+
+pub async fn show_info(api: &HubApi, repo_type: RepoType, repo_id: &str, revision: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let info = api.repo_info(repo_type, repo_id, revision).await?;
+    for (key, value) in format_info(&info) {
+        println!("{key:<14} {value}");
+    }
+    Ok(())
+}
+
+/// Filters of `search`, each one maps to a Hub API query parameter.
+#[derive(clap::Args)]
+pub struct SearchFilters {
+    /// Text to look for in repo names
+    query: Option<String>,
+
+    /// Task of the model, e.g. `text-generation`
+    #[arg(long, value_name = "TASK")]
+    pipeline_tag: Option<String>,
+
+    /// Library the repo is made for, e.g. `gguf`, `transformers`
+    #[arg(long)]
+    library: Option<String>,
+
+    /// Tags the repo must have, comma separated or repeated, e.g. `--tags 4bit`
+    #[arg(long, value_delimiter = ',')]
+    tags: Vec<String>,
+
+    /// Only list repos under this license, e.g. `apache-2.0`
+    #[arg(long)]
+    license: Option<String>,
+}
+
+impl SearchFilters {
+    fn query(&self) -> Vec<(&'static str, String)> {
+        let mut params = Vec::new();
+        if let Some(query) = &self.query {
+            params.push(("search", query.clone()));
+        }
+        if let Some(pipeline_tag) = &self.pipeline_tag {
+            params.push(("pipeline_tag", pipeline_tag.clone()));
+        }
+        if let Some(library) = &self.library {
+            params.push(("library", library.clone()));
+        }
+        params.extend(self.tags.iter().map(|t| ("filter", t.clone())));
+        if let Some(license) = &self.license {
+            params.push(("filter", format!("license:{license}")));
+        }
+        params
+    }
+}
+
+/// Hub search narrowed by the API filters, most downloaded first.
+pub async fn search(api: &HubApi, repo_type: RepoType, filters: &SearchFilters, limit: usize) -> Result<(), Box<dyn Error>> {
+    let limit_param = limit.to_string();
+    let params = filters.query();
+    let mut query: Vec<_> = params.iter().map(|(k, v)| (*k, v.as_str())).collect();
+    query.extend([("sort", "downloads"), ("direction", "-1"), ("limit", limit_param.as_str())]);
+
+    let repos = api.list_repos(repo_type, &query, Some(limit)).await?;
+    if repos.is_empty() {
+        println!("No repo found.");
+    }
+    for line in format_repo_list(&repos) {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+/// `<id> <downloads> <likes> <pipeline tag>` lines, ids padded to line up.
+fn format_repo_list(repos: &[Value]) -> Vec<String> {
+    let width = repos.iter()
+        .filter_map(|r| r["id"].as_str())
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    repos.iter()
+        .map(|r| format!(
+            "{:width$}  {:>10} downloads  {:>6} likes  {}",
+            r["id"].as_str().unwrap_or_default(),
+            r["downloads"].as_u64().unwrap_or(0),
+            r["likes"].as_u64().unwrap_or(0),
+            r["pipeline_tag"].as_str().unwrap_or("-"),
+        ))
+        .collect()
+}
+
+/// Trending repos with their size and license, fetched from each repo's info.
+pub async fn trending(api: &HubApi, repo_type: Option<RepoType>, limit: usize) -> Result<(), Box<dyn Error>> {
+    let repos: Vec<(RepoType, String)> = api.trending(repo_type, limit)
+        .await?
+        .iter()
+        .filter_map(|r| {
+            let repo_type = match r["repoType"].as_str()? {
+                "model" => RepoType::Model,
+                "dataset" => RepoType::Dataset,
+                "space" => RepoType::Space,
+                _ => return None,
+            };
+            Some((repo_type, r["repoData"]["id"].as_str()?.to_string()))
+        })
+        .collect();
+
+    let infos: Vec<_> = stream::iter(repos.iter().map(|(repo_type, id)| api.repo_info(*repo_type, id, None)))
+        .buffered(INFO_CONCURRENCY)
+        .collect()
+        .await;
+    let width = repos.iter().map(|(_, id)| id.len()).max().unwrap_or(0);
+    for (i, ((repo_type, id), info)) in repos.iter().zip(infos).enumerate() {
+        let (size, license) = match &info {
+            Ok(info) => (
+                total_size(info).map_or("-".to_string(), |s| BinaryBytes(s).to_string()),
+                license(info).unwrap_or("-".to_string()),
+            ),
+            Err(_) => ("?".to_string(), "?".to_string()),
+        };
+        println!("{:>3}. {:7} {id:width$}  {size:>10}  {license}", i + 1, repo_type.name());
+    }
+    Ok(())
+}
+
+/// License from the model card, falling back to the `license:` tag.
+pub fn license(info: &Value) -> Option<String> {
+    info["cardData"]["license"].as_str()
+        .or_else(|| info["tags"].as_array()?
+            .iter()
+            .filter_map(Value::as_str)
+            .find_map(|t| t.strip_prefix("license:")))
+        .map(str::to_string)
+}
+
+/// Sum of the sibling sizes, `None` when the API did not report them.
+fn total_size(info: &Value) -> Option<u64> {
+    info["siblings"].as_array()?
+        .iter()
+        .map(|s| s["size"].as_u64())
+        .sum()
+}
+
+fn format_info(info: &Value) -> Vec<(&'static str, String)> {
+    let text = |v: &Value| match v {
+        Value::Null => "-".to_string(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    let gated = match &info["gated"] {
+        Value::Bool(false) | Value::Null => "no".to_string(),
+        Value::Bool(true) => "yes".to_string(),
+        other => format!("yes ({})", text(other)),
+    };
+    let tags = info["tags"].as_array()
+        .map(|t| t.iter().filter_map(Value::as_str).collect::<Vec<_>>().join(", "))
+        .unwrap_or_default();
+
+    vec![
+        ("Repo", text(&info["id"])),
+        ("Revision", text(&info["sha"])),
+        ("Last modified", text(&info["lastModified"])),
+        ("Downloads", text(&info["downloads"])),
+        ("Likes", text(&info["likes"])),
+        ("Private", if info["private"].as_bool().unwrap_or(false) { "yes" } else { "no" }.to_string()),
+        ("Gated", gated),
+        ("License", license(info).unwrap_or("-".to_string())),
+        ("Files", info["siblings"].as_array().map_or(0, Vec::len).to_string()),
+        ("Total size", total_size(info).map_or("-".to_string(), |s| BinaryBytes(s).to_string())),
+        ("Tags", tags),
+    ]
+}
+
+/// `<name> <commit>` lines, names padded so commits line up.
+fn format_refs(entries: &[Value]) -> Vec<String> {
+    let width = entries.iter()
+        .filter_map(|e| e["name"].as_str())
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    entries.iter()
+        .map(|e| format!(
+            "{:width$}  {}",
+            e["name"].as_str().unwrap_or_default(),
+            e["targetCommit"].as_str().unwrap_or("-"),
+        ))
+        .collect()
+}
+
+#[test]
+fn info_total_size() {
+    let info = serde_json::json!({
+        "id": "google/gemma-2-2b-it",
+        "gated": "manual",
+        "tags": ["transformers", "license:gemma"],
+        "siblings": [{"rfilename": "a", "size": 1024}, {"rfilename": "b", "size": 1024}]
+    });
+    let lines = format_info(&info);
+    assert!(lines.contains(&("Total size", "2.00 KiB".to_string())));
+    assert!(lines.contains(&("Gated", "yes (manual)".to_string())));
+    assert!(lines.contains(&("Files", "2".to_string())));
+    assert!(lines.contains(&("License", "gemma".to_string())));
+}
+
+#[test]
+fn refs_are_aligned() {
+    let entries = serde_json::json!([
+        {"name": "main", "ref": "refs/heads/main", "targetCommit": "aaa"},
+        {"name": "fp16-branch", "ref": "refs/heads/fp16-branch", "targetCommit": "bbb"}
+    ]);
+    assert_eq!(
+        format_refs(entries.as_array().unwrap()),
+        vec!["main         aaa", "fp16-branch  bbb"]
+    );
+}
+
+#[test]
+fn search_filters_query() {
+    let filters = SearchFilters {
+        query: Some("llama".into()),
+        pipeline_tag: None,
+        library: Some("gguf".into()),
+        tags: vec!["4bit".into()],
+        license: Some("apache-2.0".into()),
+    };
+    assert_eq!(filters.query(), vec![
+        ("search", "llama".to_string()),
+        ("library", "gguf".to_string()),
+        ("filter", "4bit".to_string()),
+        ("filter", "license:apache-2.0".to_string()),
+    ]);
+}
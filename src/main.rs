@@ -1,43 +1,99 @@
-use std::fs::{create_dir_all, File};
+mod api;
+mod bulk;
+mod bundle;
+mod cache;
+mod config;
+mod diff;
+mod git;
+mod http;
+mod inspect;
+mod repo;
+mod upload;
+
+use std::collections::{HashMap, HashSet};
+use std::fs::create_dir_all;
 use std::env::{current_dir};
-use std::error::Error;
-use std::io;
-use std::io::{Stdout, Write};
-use std::path::{PathBuf};
-use std::process::Stdio;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc};
+use std::io::IsTerminal;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use futures_util::StreamExt;
+use futures_util::future::join_all;
 
-use clap::{CommandFactory, Parser};
+use clap::{Args, CommandFactory, Parser, Subcommand};
 use clap::error::ErrorKind;
-use futures_util::future::join_all;
-use reqwest::{Url, Client, ClientBuilder, get};
+use reqwest::{StatusCode, Url};
+use reqwest::header::{HeaderName, HeaderValue, CONTENT_RANGE, LAST_MODIFIED, RANGE};
 use tokio::process::Command;
-use tokio::io::AsyncWriteExt;
-use tokio::join;
-use tokio::sync::Mutex;
-use tokio::sync::Barrier;
-use indicatif::{ProgressBar, ProgressStyle};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Semaphore;
+use indicatif::{BinaryBytes, ProgressBar, ProgressStyle};
+use ring::digest::{Context, SHA256};
+
+use crate::api::{HubApi, RepoType};
+use crate::cache::MetadataCache;
+use crate::config::Config;
 
 const DEFAULT_ENDPOINT: &str = "https://hf-mirror.com/";
 const DEFAULT_PROXY: &str = "https://hg.whl.moe/";
 
 const ORIGIN_ENDPOINT: &str = "https://huggingface.co/";
+/// Suffix of a file still being downloaded, renamed away once complete.
+const PART_SUFFIX: &str = ".incomplete";
 
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     /// HuggingFace Dataset Or Model to Download, use format like `google/gemma-2-2b-it`
-    repo_id: String,
+    #[arg(required = true)]
+    repo_id: Option<String>,
 
-    /// Local directory path where the model or dataset will be stored, default is `pwd`. Note that a folder named model or dataset will be created, such as `<your_dir>/gemma-2-2b-it`.
-    #[arg(short, long, value_name = "PATH")]
-    local_dir: Option<PathBuf>,
+    #[command(flatten)]
+    download: DownloadOptions,
 
     /// HuggingFace Endpoint, default is https://hf-mirror.com/
-    #[arg(short, long, value_name = "URL")]
+    #[arg(short, long, value_name = "URL", global = true)]
     endpoint_url: Option<String>,
 
+    ///Hugging Face username for authentication. **NOT EMAIL**.
+    #[arg(long, global = true)]
+    hf_username: Option<String>,
+
+    ///Hugging Face token for authentication.
+    #[arg(long, global = true)]
+    hf_token: Option<String>,
+
+    /// Config file, default is `~/.config/hfrs/config.json` (or `$HFRS_CONFIG`)
+    #[arg(long, value_name = "PATH", global = true)]
+    config: Option<PathBuf>,
+
+    /// Ignore cached API metadata and ask the Hub again
+    #[arg(long, global = true)]
+    refresh: bool,
+
+    /// User-Agent sent with every http request, default is `hfrs/<version>`
+    #[arg(long, value_name = "UA", global = true)]
+    user_agent: Option<String>,
+
+    /// Extra http header sent with every request, like `X-Api-Key: xxx`, can be repeated
+    #[arg(long = "header", value_name = "NAME: VALUE", value_parser = http::parse_header, global = true)]
+    headers: Vec<(HeaderName, HeaderValue)>,
+
+    /// Metadata cache settings from the config file.
+    #[arg(skip)]
+    cache: Option<(PathBuf, Duration)>,
+}
+
+/// Options shared by every command that downloads repos.
+#[derive(Args, Clone)]
+struct DownloadOptions {
+    /// Local directory path where the model or dataset will be stored, default is `pwd`. Note that a folder named model or dataset will be created, such as `<your_dir>/gemma-2-2b-it`. Bulk downloads add the owner and type, such as `<your_dir>/datasets/google/some-data`.
+    #[arg(short, long, value_name = "PATH")]
+    local_dir: Option<PathBuf>,
+
     /// Large file proxy url, default is https://hg.whl.moe/
     #[arg(short, long, value_name = "URL")]
     proxy_url: Option<String>,
@@ -51,24 +107,213 @@ struct Cli {
     #[arg(long, value_name = "PATTERN")]
     exclude: Option<String>,
 
-    ///Hugging Face username for authentication. **NOT EMAIL**.
+    /// Git-free mode: list files with the API and fetch everything over http, no git or git-lfs needed.
     #[arg(long)]
-    hf_username: Option<String>,
+    no_git: bool,
 
-    ///Hugging Face token for authentication.
+    /// Clone without checkout and fetch the regular (non LFS) files concurrently over http,
+    /// much faster than git for repos with thousands of small files.
+    #[arg(long, conflicts_with = "no_git")]
+    http_small_files: bool,
+
+    /// Number of files downloaded at the same time.
+    #[arg(short, long, default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: u16,
+
+    /// Replace symlinks of the repo with a copy of the file they point to.
     #[arg(long)]
-    hf_token: Option<String>,
+    dereference: bool,
+
+    /// How many times per second progress bars are redrawn in a terminal.
+    #[arg(long, value_name = "HZ", default_value_t = 5, value_parser = clap::value_parser!(u8).range(1..))]
+    progress_rate: u8,
+
+    /// Seconds between progress lines when output is not a terminal (logs, pipes, CI).
+    #[arg(long, value_name = "SECS", default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    progress_interval: u64,
+
+    /// `allowed_licenses` from the config file.
+    #[arg(skip)]
+    allowed_licenses: Option<Vec<String>>,
 }
 
+impl DownloadOptions {
+    /// Whether a repo file passes `--include`/`--exclude`.
+    fn wants(&self, file_name: &str) -> bool {
+        self.include.as_deref().is_none_or(|p| wildcard_match(p, file_name))
+            && !self.exclude.as_deref().is_some_and(|p| wildcard_match(p, file_name))
+    }
+}
 
-async fn check_args(cli: Cli) -> Result<(Url, Url, PathBuf, String), Box<dyn std::error::Error>> {
+#[derive(Subcommand)]
+enum Commands {
+    /// Manage files of a repo you own directly on the Hub, without a local clone
+    Repo {
+        #[command(subcommand)]
+        command: repo::RepoCommands,
+    },
+
+    /// List branches, tags and converted refs of a repo, to pick a valid revision
+    Refs {
+        /// Repo to inspect, e.g. `google/gemma-2-2b-it`
+        repo_id: String,
+
+        /// Type of the repo
+        #[arg(long, value_enum, default_value_t)]
+        repo_type: RepoType,
+
+        /// Also list pull request refs (`refs/pr/N`)
+        #[arg(long)]
+        include_prs: bool,
+    },
+
+    /// Show repo metadata: downloads, likes, size, gated/private status and tags
+    Info {
+        /// Repo to inspect, e.g. `google/gemma-2-2b-it`
+        repo_id: String,
+
+        /// Type of the repo
+        #[arg(long, value_enum, default_value_t)]
+        repo_type: RepoType,
+
+        /// Branch, tag or commit to report on, default is the main branch
+        #[arg(long)]
+        revision: Option<String>,
+    },
+
+    /// Download every model and dataset of a collection
+    Collection {
+        /// Collection slug or url, e.g. `user/my-collection-66b0c0d4e5f6a7b8c9d0e1f2`
+        slug: String,
+
+        #[command(flatten)]
+        download: DownloadOptions,
+    },
+
+    /// Download or update every repo of an organization, optionally filtered by name
+    OrgSync {
+        /// Organization or user name, e.g. `openai`
+        org: String,
+
+        /// Only sync repos whose name matches this wildcard pattern, e.g. `whisper-*`
+        #[arg(long = "match", value_name = "PATTERN")]
+        pattern: Option<String>,
+
+        /// Type of the repos to sync
+        #[arg(long, value_enum, default_value_t)]
+        repo_type: RepoType,
+
+        #[command(flatten)]
+        download: DownloadOptions,
+    },
+
+    /// Search repos on the Hub, narrowed by task, library and tags
+    Search {
+        #[command(flatten)]
+        filters: inspect::SearchFilters,
+
+        /// Type of the repos to search
+        #[arg(long, value_enum, default_value_t)]
+        repo_type: RepoType,
+
+        /// Maximum number of results
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Pack a repo into a single file for air-gapped transfer, and check or unpack it on the other side
+    Bundle {
+        #[command(subcommand)]
+        command: bundle::BundleCommands,
+    },
+
+    /// Compare a local copy with a remote revision: added, removed and changed files
+    Diff {
+        /// Local copy, e.g. `./gemma-2-2b-it`
+        local_dir: PathBuf,
+
+        /// Remote repo and optional revision, e.g. `google/gemma-2-2b-it@main`
+        target: String,
+
+        /// Type of the repo
+        #[arg(long, value_enum, default_value_t)]
+        repo_type: RepoType,
+
+        /// Also compare the sha256 of LFS files, not only their size (reads every file)
+        #[arg(long)]
+        hash: bool,
+    },
+
+    /// List currently trending repos with their size and license
+    Trending {
+        /// Only list repos of this type, default is all types
+        #[arg(long, value_enum)]
+        repo_type: Option<RepoType>,
+
+        /// Maximum number of repos
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+}
+
+/// Outcome of downloading one repo.
+#[derive(Debug, Default)]
+struct DownloadSummary {
+    path: PathBuf,
+    lfs_files: usize,
+    small_files: usize,
+    skipped: usize,
+    failed: usize,
+}
+
+impl Cli {
+    /// Build the API client. Writes go to huggingface.co unless an endpoint is given, mirrors are read only.
+    /// Writes never use the metadata cache, commits must be built from the current tree.
+    fn hub_api(&self, write: bool) -> HubApi {
+        let endpoint = self.endpoint_url.clone().unwrap_or(if write {
+            ORIGIN_ENDPOINT.to_string()
+        } else {
+            DEFAULT_ENDPOINT.to_string()
+        });
+        let api = HubApi::new(&endpoint, self.hf_token.clone()).unwrap_or_else(|e| {
+            let mut cmd = Cli::command();
+            cmd.error(
+                ErrorKind::InvalidValue,
+                format!("Error while parse url: {}", e),
+            )
+                .exit()
+        });
+        match &self.cache {
+            Some((dir, ttl)) if !write => api.with_cache(MetadataCache::new(dir.clone(), *ttl, self.refresh)),
+            _ => api,
+        }
+    }
+
+    /// Hand the config file settings to the download options of whichever command runs.
+    fn apply_config(&mut self, config: &Config) {
+        self.cache = Config::cache_dir().map(|dir| (dir.join("api"), config.metadata_ttl));
+
+        let download = match &mut self.command {
+            None => &mut self.download,
+            Some(Commands::Collection { download, .. }) | Some(Commands::OrgSync { download, .. }) => download,
+            Some(Commands::Bundle { command }) => match command.download_options() {
+                Some(download) => download,
+                None => return,
+            },
+            Some(_) => return,
+        };
+        download.allowed_licenses = config.allowed_licenses.clone();
+    }
+}
+
+
+async fn check_args(repo_id: &str, repo_type: RepoType, endpoint: Option<String>, opts: &DownloadOptions) -> Result<(Url, Url, PathBuf, String), Box<dyn std::error::Error>> {
     let endpoint_url;
     let proxy_url;
     let file_path;
     let save_path;
 
-
-    let splits: Vec<&str> = cli.repo_id.trim().split("/").collect();
+    let splits: Vec<&str> = repo_id.trim().split("/").collect();
 
     // println!("{splits:?}");
 
@@ -78,13 +323,13 @@ async fn check_args(cli: Cli) -> Result<(Url, Url, PathBuf, String), Box<dyn std
         println!("Parsing {author}:{item}...");
 
 
-        let endpoint = (cli.endpoint_url
+        let endpoint = endpoint
             .unwrap_or(DEFAULT_ENDPOINT.to_string()
-            ));
-        let proxy = (cli.proxy_url
+            );
+        let proxy = opts.proxy_url.clone()
             .unwrap_or(DEFAULT_PROXY.to_string()
-            ));
-        file_path = format!("{author}/{item}");
+            );
+        file_path = format!("{}{author}/{item}", repo_type.url_prefix());
 
         endpoint_url = Url::parse(&
         if endpoint.ends_with("/") {
@@ -120,7 +365,7 @@ async fn check_args(cli: Cli) -> Result<(Url, Url, PathBuf, String), Box<dyn std
         });
 
 
-        println!("Target url is {}, proxy url is {}", endpoint_url.to_string(), proxy_url.to_string());
+        println!("Target url is {}, proxy url is {}", endpoint_url, proxy_url);
         println!("Checking endpoint url...");
         if !(check_url_status(&endpoint_url)
             .await?
@@ -128,7 +373,7 @@ async fn check_args(cli: Cli) -> Result<(Url, Url, PathBuf, String), Box<dyn std
             let mut cmd = Cli::command();
             cmd.error(
                 ErrorKind::ValueValidation,
-                format!("{} not return 200, please check network!", endpoint_url.to_string()),
+                format!("{} not return 200, please check network!", endpoint_url),
             )
                 .exit();
         }
@@ -140,18 +385,18 @@ async fn check_args(cli: Cli) -> Result<(Url, Url, PathBuf, String), Box<dyn std
             let mut cmd = Cli::command();
             cmd.error(
                 ErrorKind::ValueValidation,
-                format!("{} not return 200, please check network!", proxy_url.to_string()),
+                format!("{} not return 200, please check network!", proxy_url),
             )
                 .exit();
         }
 
-        save_path = cli.local_dir
+        save_path = opts.local_dir.clone()
             .unwrap_or(current_dir().unwrap())
             .join(item);
 
         if !save_path.exists() {
             println!("Path {} does not exist. Creating it now.", save_path.to_str().unwrap());
-            let _ = create_dir_all(&save_path).unwrap_or_else(|e| {
+            create_dir_all(&save_path).unwrap_or_else(|e| {
                 let mut cmd = Cli::command();
                 cmd.error(
                     ErrorKind::InvalidValue,
@@ -167,7 +412,7 @@ async fn check_args(cli: Cli) -> Result<(Url, Url, PathBuf, String), Box<dyn std
         let mut cmd = Cli::command();
         cmd.error(
             ErrorKind::InvalidValue,
-            format!("{} is not a valid repo id!", cli.repo_id),
+            format!("{} is not a valid repo id!", repo_id),
         )
             .exit();
     }
@@ -177,148 +422,460 @@ async fn check_args(cli: Cli) -> Result<(Url, Url, PathBuf, String), Box<dyn std
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
-    let (endpoint, proxy, save_path, file_path): (Url, Url, PathBuf, String) = check_args(cli).await?;
-
-    println!("Check git and lfs...");
-    check_command_exists("git").await;
-    check_command_exists("git-lfs").await;
-    check_repo_authority(&endpoint, None, None).await.expect("Check authority fail!");
-
-    let ret = String::from_utf8(if save_path
-        .join(".git")
-        .exists()
-    {
-        println!("Executing `git pull`...");
-        Command::new(r"git")
-            .current_dir(&save_path)
-            .env("GIT_LFS_SKIP_SMUDGE", "1")
-            .arg("pull")
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
-            .output()
-            .await
-            .expect("git pull fail!")
-            .stderr
+    let mut cli = Cli::parse();
+    let config = Config::load(cli.config.as_deref())?;
+    cli.apply_config(&config);
+    http::configure(cli.user_agent.as_deref(), &cli.headers)?;
+
+    if let Some(command) = &cli.command {
+        return match command {
+            Commands::Repo { command } => repo::run(command, &cli.hub_api(command.writes())).await,
+            Commands::Refs { repo_id, repo_type, include_prs } => {
+                inspect::show_refs(&cli.hub_api(false), *repo_type, repo_id, *include_prs).await
+            }
+            Commands::Info { repo_id, repo_type, revision } => {
+                inspect::show_info(&cli.hub_api(false), *repo_type, repo_id, revision.as_deref()).await
+            }
+            Commands::Collection { slug, download } => {
+                bulk::download_collection(&cli.hub_api(false), slug, cli.endpoint_url.clone(), download).await
+            }
+            Commands::Search { filters, repo_type, limit } => {
+                inspect::search(&cli.hub_api(false), *repo_type, filters, *limit).await
+            }
+            Commands::Bundle { command } => {
+                bundle::run(command, &cli.hub_api(false), cli.endpoint_url.clone()).await
+            }
+            Commands::Diff { local_dir, target, repo_type, hash } => {
+                diff::diff(&cli.hub_api(false), local_dir, target, *repo_type, *hash).await
+            }
+            Commands::Trending { repo_type, limit } => {
+                inspect::trending(&cli.hub_api(false), *repo_type, *limit).await
+            }
+            Commands::OrgSync { org, pattern, repo_type, download } => {
+                bulk::org_sync(&cli.hub_api(false), org, *repo_type, pattern.as_deref(), cli.endpoint_url.clone(), download).await
+            }
+        };
+    }
+
+    let repo_id = cli.repo_id.clone().unwrap_or_default();
+    download_repo(&cli.hub_api(false), &repo_id, RepoType::Model, cli.endpoint_url.clone(), &cli.download).await?;
+    Ok(())
+}
+
+/// Clone (or pull) a repo without LFS objects, then fetch the LFS files through the proxy.
+async fn download_repo(api: &HubApi, repo_id: &str, repo_type: RepoType, endpoint: Option<String>, opts: &DownloadOptions) -> Result<DownloadSummary, Box<dyn std::error::Error>> {
+    if let Some(allowed) = &opts.allowed_licenses {
+        check_license(api, repo_id, repo_type, allowed).await?;
+    }
+
+    let (endpoint, proxy, save_path, _file_path): (Url, Url, PathBuf, String) = check_args(repo_id, repo_type, endpoint, opts).await?;
+
+    let mut summary = DownloadSummary { path: save_path.clone(), ..Default::default() };
+    // The remote tree, only listed without git, to recognize symlinks.
+    let mut tree = Vec::new();
+    // sha256 of every LFS file, to verify what the proxy sent.
+    let mut lfs_oids = HashMap::new();
+    let (lfs_files, small_files) = if opts.no_git {
+        println!("Listing files of {repo_id}...");
+        tree = api.list_tree(repo_type, repo_id, "main", "", true).await?;
+        let (lfs, small): (Vec<_>, Vec<_>) = tree.iter()
+            .filter(|e| e["type"].as_str() == Some("file"))
+            .partition(|e| e["lfs"].is_object());
+        lfs_oids.extend(lfs.iter().filter_map(|e| Some((e["path"].as_str()?.to_string(), e["lfs"]["oid"].as_str()?.to_string()))));
+        let paths = |entries: Vec<&serde_json::Value>| -> Vec<String> {
+            entries.iter().filter_map(|e| e["path"].as_str()).map(str::to_string).collect()
+        };
+        (paths(lfs), paths(small))
     } else {
-        println!("Executing `git clone {}`...", endpoint.to_string());
-        Command::new(r"git")
-            .env("GIT_LFS_SKIP_SMUDGE", "1")
-            .arg("clone")
-            .arg(endpoint.to_string())
-            .arg(save_path.to_str().expect("Save path is not a Valid utf8 path"))
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
+        println!("Check git and lfs...");
+        for command in ["git", "git-lfs"] {
+            if !check_command_exists(command).await {
+                return Err(format!("{command} is not installed or not in PATH, install it or use --no-git").into());
+            }
+        }
+        let status = check_repo_authority(&endpoint, None, None).await?;
+        if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            // git and the proxy never get the token, so there is no token to explain here.
+            return Err(format!(
+                "{endpoint} answered {status}: {repo_id} is private or gated, downloads through the mirror are not authenticated"
+            ).into());
+        }
+
+        let cloned_without_checkout = git::sync(&endpoint, &save_path, !opts.http_small_files).await?;
+        // Fresh clone without checkout: fetch the tracked regular files over http instead.
+        let small_files = if cloned_without_checkout {
+            git::regular_files(&save_path).await?
+        } else {
+            Vec::new()
+        };
+
+        let output = git::git(Some(&save_path))
+            .arg("lfs")
+            .arg("ls-files")
+            .arg("-l")
             .output()
-            .await
-            .expect("git clone fail!")
-            .stderr
-    })?;
-    println!("{ret}");
-    let output = Command::new("git")
-        .current_dir(&save_path)
-        .env("GIT_LFS_SKIP_SMUDGE", "1")
-        .arg("lfs")
-        .arg("ls-files")
-        .output()
-        .await?;
-
-    let lfs = String::from_utf8(output.stdout)
-        .expect("Read utf8 output fail!");
-
-    println!("{lfs}");
-
-    let lfs_vec: Vec<_> = lfs.lines().collect();
+            .await?;
+
+        let lfs = String::from_utf8_lossy(&output.stdout);
+
+        println!("{lfs}");
+
+        let lfs_files: Vec<_> = lfs.lines()
+            .map(|line| git::parse_lfs_line(line)
+                .unwrap_or_else(|| panic!("Cant parse lfs list:{line}"))
+            )
+            .map(|(oid, file_name)| {
+                lfs_oids.insert(file_name.clone(), oid);
+                file_name
+            })
+            .collect();
+        let lfs_set: HashSet<&String> = lfs_files.iter().collect();
+        let small_files = small_files.into_iter().filter(|f| !lfs_set.contains(f)).collect();
+        if !cloned_without_checkout {
+            // Files git checked out itself, downloaded ones get Last-Modified instead.
+            let checked_out: Vec<_> = git::regular_files(&save_path).await?
+                .into_iter()
+                .filter(|f| !lfs_set.contains(f))
+                .collect();
+            git::stamp_commit_dates(&save_path, &checked_out).await?;
+            git::refresh_index(&save_path).await?;
+        }
+        (lfs_files, small_files)
+    };
+
+    let mut wanted = |files: Vec<String>| -> Vec<String> {
+        files.into_iter()
+            .filter(|file_name| {
+                let wanted = opts.wants(file_name);
+                if !wanted {
+                    summary.skipped += 1;
+                }
+                wanted
+            })
+            .collect()
+    };
+    let lfs_vec = wanted(lfs_files);
+    let small_vec = wanted(small_files);
+    let jobs = Arc::new(Semaphore::new(opts.jobs as usize));
+
     let files_count = lfs_vec.len();
+    summary.lfs_files = files_count;
     let bar = Arc::new(indicatif::MultiProgress::with_draw_target(
-        indicatif::ProgressDrawTarget::stderr_with_hz(5)
+        indicatif::ProgressDrawTarget::stderr_with_hz(opts.progress_rate)
     ));
+    // Bars are hidden when stderr is not a terminal, print plain lines there instead.
+    let plain_interval = (!std::io::stderr().is_terminal())
+        .then(|| Duration::from_secs(opts.progress_interval));
 
-    let tasks:Vec<_> = lfs_vec.iter().enumerate().map(|(i, line)| {
-        let file_name = line
-            .split_once("-")
-            .expect(&format!("Cant parse lfs list:{line}"))
-            .1
-            .trim()
-            .to_string()
-            ;
+    let tasks:Vec<_> = lfs_vec.into_iter().enumerate().map(|(i, file_name)| {
         let proxy = proxy.clone();
         let endpoint = endpoint.clone();
         let save_path = save_path.clone();
         let bar = Arc::clone(&bar);
-        let url = format!("{}{}{}/resolve/main/{}",
-                          proxy.to_string(),
-                          ORIGIN_ENDPOINT,
-                          endpoint.path()
+        let jobs = Arc::clone(&jobs);
+        let url = lfs_url(&proxy, endpoint.path()
                               .strip_prefix("/")
                               .unwrap()
                               .strip_suffix("/")
-                              .unwrap()
-                          ,
-                          file_name
-        );
+                              .unwrap(),
+                          &file_name
+        ).to_string();
+        let oid = lfs_oids.get(&file_name).cloned();
         tokio::spawn(async move {
-            download_files(&url, &save_path.join(file_name), i, files_count, bar)
+            let _permit = jobs.acquire().await;
+            download_files(&url, &save_path.join(file_name), oid.as_deref(), i, files_count, bar, plain_interval)
                 .await
-                .expect("Download fail...");
+                .map_err(|e| println!("[{i}] Download fail: {e}"))
+                .is_ok()
         })
     }).collect();
 
 
+    summary.small_files = small_vec.len();
+    let small_bar = if small_vec.is_empty() {
+        ProgressBar::hidden()
+    } else {
+        println!("Fetching {} small file(s) over http...", small_vec.len());
+        let small_bar = bar.add(ProgressBar::new(small_vec.len() as u64));
+        small_bar.set_style(ProgressStyle::with_template("small files {bar:70.green/red} {pos}/{len} [{elapsed_precise}/{eta_precise}] {msg}")
+            .unwrap()
+        );
+        small_bar
+    };
+    // Not spawned: they borrow the API client, which carries the token of private repos.
+    let small_tasks = small_vec.iter().map(|file_name| {
+        let path = save_path.join(file_name);
+        let small_bar = small_bar.clone();
+        let jobs = Arc::clone(&jobs);
+        async move {
+            let _permit = jobs.acquire().await;
+            let ret = fetch_small_file(api, repo_type, repo_id, file_name, &path).await;
+            small_bar.inc(1);
+            ret.map_err(|e| println!("Download fail {file_name}: {e}")).is_ok()
+        }
+    });
+    // LFS tasks run meanwhile, they are spawned.
+    let small_ok = join_all(small_tasks).await;
+    summary.failed += small_ok.iter().filter(|ok| !**ok).count();
     for task in tasks {
-        task.await.unwrap();
+        if !task.await? {
+            summary.failed += 1;
+        }
+    }
+    small_bar.finish();
+
+    if !opts.no_git {
+        if summary.small_files > 0 {
+            // The index was reset to HEAD without checkout, refresh its stat info now that files are here.
+            git::refresh_index(&save_path).await?;
+        }
+        // After the LFS downloads, so dereferenced links get the real content.
+        git::fix_symlinks(&save_path, opts.dereference).await?;
+    } else {
+        git::restore_links(&save_path, git::guess_links(&save_path, &small_vec, &tree), opts.dereference)?;
+    }
+    Ok(summary)
+}
+
+
+/// `<proxy><origin><repo>/resolve/main/<file>`, with every path segment of `file`
+/// percent encoded so non-ASCII, `#` or `?` in names survive.
+fn lfs_url(proxy: &Url, repo_path: &str, file_name: &str) -> Url {
+    let mut url = Url::parse(&format!("{}{}{}/resolve/main", proxy, ORIGIN_ENDPOINT, repo_path))
+        .expect("Invalid proxy url");
+    url.path_segments_mut()
+        .expect("Proxy url cannot be a base")
+        .extend(file_name.split('/'));
+    url
+}
+
+/// Refuse repos whose license is not in the allow-list of the config file.
+async fn check_license(api: &HubApi, repo_id: &str, repo_type: RepoType, allowed: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let info = api.repo_info(repo_type, repo_id, None).await?;
+    match inspect::license(&info) {
+        Some(license) if allowed.iter().any(|a| a.eq_ignore_ascii_case(&license)) => {
+            println!("License {license} is allowed.");
+            Ok(())
+        }
+        Some(license) => Err(format!("{repo_id} is licensed under `{license}`, which is not in allowed_licenses").into()),
+        None => Err(format!("{repo_id} declares no license, allowed_licenses is set so it is refused").into()),
+    }
+}
+
+/// `start` and `total` of a `Content-Range` header, `bytes start-end/total`,
+/// or `bytes */total` as sent with a 416.
+fn parse_content_range(value: &str) -> Option<(Option<u64>, Option<u64>)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let start = match range {
+        "*" => None,
+        range => Some(range.split_once('-')?.0.parse().ok()?),
+    };
+    Some((start, total.parse().ok()))
+}
+
+/// Feed the content of `path` to `ctx`, to continue hashing a resumed download.
+async fn hash_existing(ctx: &mut Context, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        ctx.update(&buf[..n]);
+    }
+}
+
+/// Check a finished part file against the LFS `oid`, discarding it on mismatch.
+async fn verify_part(part_path: &Path, url: &str, oid: Option<&str>, digest: Option<Context>) -> Result<(), Box<dyn std::error::Error>> {
+    let (Some(oid), Some(digest)) = (oid, digest) else {
+        return Ok(());
+    };
+    let sha256 = hex(digest.finish().as_ref());
+    if sha256 != oid {
+        tokio::fs::remove_file(part_path).await?;
+        return Err(format!("{url} has sha256 {sha256} instead of {oid}, discarded; run again to download it from scratch").into());
     }
     Ok(())
 }
 
+/// Download `url` to `path`, resuming a previous attempt when the server honors
+/// ranges and checking the result against the LFS `oid` (sha256) when given.
+async fn download_files(url: &str, path: &PathBuf, oid: Option<&str>, task_count: usize, _total_task: usize, bar_m: Arc<indicatif::MultiProgress>, plain_interval: Option<Duration>) -> Result<(), Box<dyn std::error::Error>> {
+    // Data is written next to the file and renamed when complete, a leftover is resumed with a range request.
+    let part_path = PathBuf::from(format!("{}{PART_SUFFIX}", path.display()));
+    let offset = tokio::fs::metadata(&part_path).await.map_or(0, |m| m.len());
 
-async fn download_files(url: &str, path: &PathBuf, task_count: usize, total_task: usize, bar_m: Arc<indicatif::MultiProgress>) -> Result<(), Box<dyn std::error::Error>> {
-    let mut file = tokio::fs::File::create(path).await?;
-    let resp = get(url).await?;
+    let mut request = http::client().get(url);
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={offset}-"));
+    }
+    let mut resp = http::send(request).await?;
+
+    let content_range = resp.headers()
+        .get(CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_content_range);
+
+    // The previous run got every byte but stopped before the rename.
+    if offset > 0 && resp.status() == StatusCode::RANGE_NOT_SATISFIABLE && content_range.and_then(|(_, total)| total) == Some(offset) {
+        let mut digest = oid.map(|_| Context::new(&SHA256));
+        if let Some(digest) = &mut digest {
+            hash_existing(digest, &part_path).await?;
+        }
+        verify_part(&part_path, url, oid, digest).await?;
+        tokio::fs::rename(&part_path, path).await?;
+        println!("[{task_count}] Completed {url} left by an earlier run");
+        return Ok(());
+    }
+
+    // Some mirrors answer 200 with the whole body, or a different range; appending that would corrupt the file.
+    let resumed = offset > 0 && resp.status() == StatusCode::PARTIAL_CONTENT && content_range.is_some_and(|(start, _)| start == Some(offset));
+    if offset > 0 && !resumed {
+        println!("[{task_count}] {url} did not honor the range request, restarting from scratch");
+        if resp.status() != StatusCode::OK {
+            resp = http::send(http::client().get(url)).await?;
+        }
+    }
 
     if !resp.status().is_success() {
-        println!("Cant download {} with status {}", url, resp.status().to_string());
-        return Ok(());
+        return Err(format!("Cant download {} with status {}", url, resp.status()).into());
+    }
+
+    let remote_mtime = resp.headers()
+        .get(LAST_MODIFIED)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_http_date);
+    let remote_len = if resumed {
+        content_range.and_then(|(_, total)| total)
+    } else {
+        resp.content_length()
+    };
+
+    // Files left over from a previous run have the remote size and mtime, lfs pointers don't.
+    if let (Some(len), Ok(meta)) = (remote_len, tokio::fs::metadata(path).await) {
+        let same_mtime = remote_mtime.is_none_or(|t| meta.modified().ok() == Some(t));
+        if meta.len() == len && same_mtime {
+            println!("[{task_count}] Skip {}, already downloaded", url);
+            let _ = tokio::fs::remove_file(&part_path).await;
+            return Ok(());
+        }
+    }
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
     }
+    // A resumed download may belong to an older revision of the file, the
+    // sha256 over the whole part catches that before it is renamed into place.
+    let mut digest = oid.map(|_| Context::new(&SHA256));
+    let mut file = if resumed {
+        if let Some(digest) = &mut digest {
+            hash_existing(digest, &part_path).await?;
+        }
+        tokio::fs::OpenOptions::new().append(true).open(&part_path).await?
+    } else {
+        tokio::fs::File::create(&part_path).await?
+    };
 
-    let total_bytes: u64 = resp.content_length().unwrap_or(10485760);
-    let mut count_bytes: f64 = 0.;
+    let total_bytes: u64 = remote_len.unwrap_or(10485760);
 
     let mut stream = resp.bytes_stream();
     let bar = bar_m.add(ProgressBar::new(total_bytes));
-    bar.set_style(ProgressStyle::with_template( &(format!("{}",path.file_name().unwrap().to_str().unwrap()) +" {bar:70.green/red} {binary_bytes:>7}/{binary_total_bytes:7} {bytes_per_sec} [{elapsed_precise}/{eta_precise}] {msg}"))
+    bar.set_style(ProgressStyle::with_template( &(path.file_name().unwrap().to_str().unwrap().to_string() +" {bar:70.green/red} {binary_bytes:>7}/{binary_total_bytes:7} {bytes_per_sec} [{elapsed_precise}/{eta_precise}] {msg}"))
         .unwrap()
         );
 
-
-    println!("\r[{task_count}] Start downloading {url}...");
+    if resumed {
+        bar.set_position(offset);
+        println!("\r[{task_count}] Resume downloading {url} from {}...", BinaryBytes(offset));
+    } else {
+        println!("\r[{task_count}] Start downloading {url}...");
+    }
+    let mut last_line = Instant::now();
     while let Some(chunk_result) = stream.next().await {
         let chunk = chunk_result?;
         file.write_all(&chunk).await?;
-        count_bytes += chunk.len() as f64;
+        if let Some(digest) = &mut digest {
+            digest.update(&chunk);
+        }
         //进度条？
         bar.inc(chunk.len() as u64);
 
+        if let Some(interval) = plain_interval {
+            if last_line.elapsed() >= interval {
+                last_line = Instant::now();
+                println!(
+                    "[{task_count}] {} {}/{} {}/s",
+                    path.file_name().unwrap().to_string_lossy(),
+                    BinaryBytes(bar.position()),
+                    BinaryBytes(total_bytes),
+                    BinaryBytes(bar.per_sec() as u64)
+                );
+            }
+        }
     }
 
     file.flush().await?;
+    let written = file.metadata().await?.len();
+    if remote_len.is_some_and(|len| len != written) {
+        drop(file);
+        tokio::fs::remove_file(&part_path).await?;
+        return Err(format!("{url} gave {written} bytes instead of {}, discarded", remote_len.unwrap_or_default()).into());
+    }
+    if let Some(mtime) = remote_mtime {
+        file.into_std().await.set_modified(mtime)?;
+    } else {
+        drop(file);
+    }
+    verify_part(&part_path, url, oid, digest).await?;
+    tokio::fs::rename(&part_path, path).await?;
 
     println!("[{task_count}] Downloaded {}", url);
     Ok(())
 }
 
+/// GET of a small (non LFS) file with the API client, skipped when size and mtime already match.
+async fn fetch_small_file(api: &HubApi, repo_type: RepoType, repo_id: &str, file_name: &str, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let resp = api.download(repo_type, repo_id, "main", file_name).await?;
+    let remote_mtime = resp.headers()
+        .get(LAST_MODIFIED)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_http_date);
+    // A symlink restored by an earlier run, writing through it would clobber its target.
+    if tokio::fs::symlink_metadata(path).await.is_ok_and(|m| m.file_type().is_symlink()) {
+        tokio::fs::remove_file(path).await?;
+    }
+    if let (Some(len), Ok(meta)) = (resp.content_length(), tokio::fs::metadata(path).await) {
+        if meta.len() == len && remote_mtime.is_none_or(|t| meta.modified().ok() == Some(t)) {
+            return Ok(());
+        }
+    }
+
+    let content = resp.bytes().await?;
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let mut file = tokio::fs::File::create(path).await?;
+    file.write_all(&content).await?;
+    file.flush().await?;
+    if let Some(mtime) = remote_mtime {
+        file.into_std().await.set_modified(mtime)?;
+    }
+    Ok(())
+}
+
 async fn check_url_status(url: &Url) -> Result<bool, Box<dyn std::error::Error>> {
-    let success = get(
-        url.clone()
-    )
+    let success = http::send(http::client().get(url.clone()))
         .await?
         .status()
         .is_success();
     Ok(success)
 }
-async fn check_repo_authority(endpoint: &Url, hf_name: Option<String>, hf_token: Option<String>) -> Result<bool, Box<dyn std::error::Error>> {
+async fn check_repo_authority(endpoint: &Url, _hf_name: Option<String>, _hf_token: Option<String>) -> Result<StatusCode, Box<dyn std::error::Error>> {
     let ref_url = endpoint.join("info/refs?service=git-upload-pack").unwrap();
-    Ok(check_url_status(&ref_url).await.expect(&format!("Cant authority target repo {}", ref_url.to_string())))
+    let resp = http::send(http::client().get(ref_url.clone()))
+        .await
+        .map_err(|e| format!("Cant authority target repo {ref_url}: {e}"))?;
+    Ok(resp.status())
 }
 
 async fn check_command_exists(command: &str) -> bool {
@@ -327,36 +884,104 @@ async fn check_command_exists(command: &str) -> bool {
             .arg(command)
             .output()
             .await
-            .expect(&format!("{command} not exist!"))
+            .unwrap_or_else(|_| panic!("{command} not exist!"))
     } else {
         Command::new("which")
             .arg(command)
             .output()
             .await
-            .expect(&format!("{command} not exist!"))
+            .unwrap_or_else(|_| panic!("{command} not exist!"))
     };
 
 
     check_command.status.success()
 }
 
-fn save(text: &str, file_path: &str) {
-    // let file_path = "output.txt";
+/// Lowercase hex of a digest.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
 
-    // 创建并打开文件
-    let mut file = match File::create(file_path) {
-        Ok(file) => file,
-        Err(e) => {
-            eprintln!("Error creating file: {}", e);
-            return;
-        }
+/// Parse an IMF-fixdate as sent in `Last-Modified`, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn parse_http_date(date: &str) -> Option<SystemTime> {
+    let parts: Vec<&str> = date.split_whitespace().collect();
+    let [_weekday, day, month, year, time, "GMT"] = parts[..] else {
+        return None;
     };
+    let month = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"]
+        .iter()
+        .position(|m| *m == month)? as i64 + 1;
+    let (day, year): (i64, i64) = (day.parse().ok()?, year.parse().ok()?);
+    let hms: Vec<u64> = time.split(':').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+    let [h, m, sec] = hms[..] else {
+        return None;
+    };
+
+    // Days since 1970-01-01 for a proleptic Gregorian date (H. Hinnant's days_from_civil).
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = u64::try_from(era * 146097 + doe - 719468).ok()?;
 
-    // 将字符串写入文件
-    match file.write_all(text.as_bytes()) {
-        Ok(_) => println!("Text successfully written to {}", file_path),
-        Err(e) => eprintln!("Error writing to file: {}", e),
+    Some(UNIX_EPOCH + Duration::from_secs(days * 86400 + h * 3600 + m * 60 + sec))
+}
+
+/// Glob style match where `*` matches any run of characters (including `/`) and `?` a single one.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let (p, t): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
     }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+#[test]
+fn wildcard_patterns() {
+    assert!(wildcard_match("*.safetensors", "model-00001-of-00002.safetensors"));
+    assert!(wildcard_match("vae/*", "vae/diffusion_pytorch_model.bin"));
+    assert!(!wildcard_match("vae/*", "unet/diffusion_pytorch_model.bin"));
+    assert!(wildcard_match("model-?????-of-*", "model-00001-of-00002.safetensors"));
+    assert!(!wildcard_match("*.bin", "model.safetensors"));
+}
+
+#[test]
+fn content_ranges() {
+    assert_eq!(parse_content_range("bytes 100-999/1000"), Some((Some(100), Some(1000))));
+    assert_eq!(parse_content_range("bytes 100-999/*"), Some((Some(100), None)));
+    assert_eq!(parse_content_range("bytes */1000"), Some((None, Some(1000))));
+    assert_eq!(parse_content_range("items 0-1/2"), None);
+}
+
+#[test]
+fn http_dates() {
+    assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(UNIX_EPOCH + Duration::from_secs(784111777)));
+    assert_eq!(parse_http_date("Thu, 29 Feb 2024 00:00:00 GMT"), Some(UNIX_EPOCH + Duration::from_secs(1709164800)));
+    assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+}
+
+#[test]
+fn lfs_url_encoding() {
+    let proxy = Url::parse(DEFAULT_PROXY).unwrap();
+    assert_eq!(
+        lfs_url(&proxy, "datasets/org/data", "中文/a #1.parquet").as_str(),
+        "https://hg.whl.moe/https://huggingface.co/datasets/org/data/resolve/main/%E4%B8%AD%E6%96%87/a%20%231.parquet"
+    );
 }
 
 #[test]
@@ -367,7 +992,7 @@ fn verify_cli() {
 
 #[tokio::test]
 async fn test_download() {
-    let urls = vec![
+    let urls = [
         "https://speed.cloudflare.com/__down?during=download&bytes=10485760",
     ];
     // let barrier = Arc::new(Barrier::new(urls.len()));
@@ -381,9 +1006,11 @@ async fn test_download() {
                 download_files(
                     &url,
                     &PathBuf::from(&format!("./tmp_{i}")),
+                    None,
                     i,
                     5,
                     bar,
+                    None,
                 ).await.unwrap();
             })
         }
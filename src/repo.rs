@@ -0,0 +1,229 @@
+use std::error::Error;
+use std::path::PathBuf;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use clap::{CommandFactory, Subcommand};
+use clap::error::ErrorKind;
+use serde_json::Value;
+
+use crate::Cli;
+use crate::api::{CommitOperation, HubApi, RepoType};
+use crate::upload;
+
+#[derive(Subcommand)]
+pub enum RepoCommands {
+    /// Delete files (or folders with `-r`) from a repo in a single commit
+    Rm {
+        /// Repo to modify, e.g. `me/my-model`
+        repo_id: String,
+
+        /// Paths inside the repo to delete
+        #[arg(required = true)]
+        paths: Vec<String>,
+
+        /// Delete folders and everything under them
+        #[arg(short, long)]
+        recursive: bool,
+
+        #[command(flatten)]
+        target: CommitTarget,
+    },
+
+    /// Move or rename a file or folder inside a repo
+    Mv {
+        /// Repo to modify, e.g. `me/my-model`
+        repo_id: String,
+
+        /// Current path inside the repo
+        src: String,
+
+        /// New path inside the repo
+        dst: String,
+
+        #[command(flatten)]
+        target: CommitTarget,
+    },
+
+    /// Upload a file or folder in a single commit, large files through LFS
+    Upload {
+        /// Repo to modify, e.g. `me/my-model`
+        repo_id: String,
+
+        /// Local file or folder to upload
+        local_path: PathBuf,
+
+        /// Destination inside the repo, default is the file name (or the root for a folder)
+        path_in_repo: Option<String>,
+
+        #[command(flatten)]
+        target: CommitTarget,
+    },
+
+    /// Show the commit history of a repo
+    Log {
+        /// Repo to inspect, e.g. `google/gemma-2-2b-it`
+        repo_id: String,
+
+        /// Branch, tag or commit to start from
+        #[arg(long, default_value = "main")]
+        revision: String,
+
+        /// Type of the repo
+        #[arg(long, value_enum, default_value_t)]
+        repo_type: RepoType,
+
+        /// Maximum number of commits to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+}
+
+#[derive(clap::Args)]
+pub struct CommitTarget {
+    /// Branch to commit to
+    #[arg(long, default_value = "main")]
+    revision: String,
+
+    /// Type of the repo
+    #[arg(long, value_enum, default_value_t)]
+    repo_type: RepoType,
+
+    /// Commit message, a default one is generated when omitted
+    #[arg(short, long)]
+    message: Option<String>,
+}
+
+impl RepoCommands {
+    /// Whether the command creates a commit on the Hub.
+    pub fn writes(&self) -> bool {
+        !matches!(self, RepoCommands::Log { .. })
+    }
+}
+
+pub async fn run(command: &RepoCommands, api: &HubApi) -> Result<(), Box<dyn Error>> {
+    if command.writes() && !api.has_token() {
+        let mut cmd = Cli::command();
+        cmd.error(
+            ErrorKind::MissingRequiredArgument,
+            "Modifying a repo needs a write token, pass it with --hf-token",
+        )
+            .exit();
+    }
+
+    match command {
+        RepoCommands::Rm { repo_id, paths, recursive, target } => {
+            let operations: Vec<_> = paths.iter()
+                .map(|p| p.trim_matches('/').to_string())
+                .map(|path| if *recursive {
+                    CommitOperation::DeleteFolder { path }
+                } else {
+                    CommitOperation::DeleteFile { path }
+                })
+                .collect();
+            let summary = target.message.clone()
+                .unwrap_or(format!("Delete {}", paths.join(", ")));
+            commit(api, repo_id, target, &summary, &operations).await
+        }
+        RepoCommands::Mv { repo_id, src, dst, target } => {
+            let src = src.trim_matches('/');
+            let dst = dst.trim_matches('/');
+            let operations = move_operations(api, repo_id, target, src, dst).await?;
+            let summary = target.message.clone()
+                .unwrap_or(format!("Move {src} to {dst}"));
+            commit(api, repo_id, target, &summary, &operations).await
+        }
+        RepoCommands::Upload { repo_id, local_path, path_in_repo, target } => {
+            let operations = upload::upload_operations(
+                api,
+                target.repo_type,
+                repo_id,
+                &target.revision,
+                local_path,
+                path_in_repo.as_deref(),
+            ).await?;
+            let summary = target.message.clone()
+                .unwrap_or(format!("Upload {}", local_path.display()));
+            commit(api, repo_id, target, &summary, &operations).await
+        }
+        RepoCommands::Log { repo_id, revision, repo_type, limit } => {
+            let commits = api.list_commits(*repo_type, repo_id, revision, Some(*limit)).await?;
+            for c in &commits {
+                println!("{}", format_commit(c));
+            }
+            Ok(())
+        }
+    }
+}
+
+async fn commit(api: &HubApi, repo_id: &str, target: &CommitTarget, summary: &str, operations: &[CommitOperation]) -> Result<(), Box<dyn Error>> {
+    println!("Committing {} operation(s) to {repo_id}@{}...", operations.len(), target.revision);
+    let ret = api.create_commit(target.repo_type, repo_id, &target.revision, summary, operations).await?;
+    println!("Committed {}", ret["commitUrl"].as_str().or(ret["commitOid"].as_str()).unwrap_or("successfully"));
+    Ok(())
+}
+
+/// Re-add every file under `src` at `dst`, then delete `src`. LFS objects are
+/// referenced by oid so nothing large is transferred; small files are copied.
+async fn move_operations(api: &HubApi, repo_id: &str, target: &CommitTarget, src: &str, dst: &str) -> Result<Vec<CommitOperation>, Box<dyn Error>> {
+    let parent = src.rsplit_once('/').map(|(p, _)| p).unwrap_or("");
+    let entry = api.list_tree(target.repo_type, repo_id, &target.revision, parent, false)
+        .await?
+        .into_iter()
+        .find(|e| e["path"].as_str() == Some(src))
+        .ok_or(format!("{src} not found in {repo_id}@{}", target.revision))?;
+
+    let is_dir = entry["type"].as_str() == Some("directory");
+    let files = if is_dir {
+        api.list_tree(target.repo_type, repo_id, &target.revision, src, true)
+            .await?
+            .into_iter()
+            .filter(|e| e["type"].as_str() == Some("file"))
+            .collect()
+    } else {
+        vec![entry]
+    };
+
+    let mut operations = Vec::with_capacity(files.len() + 1);
+    for file in files {
+        let path = file["path"].as_str().ok_or("Tree entry without path")?;
+        let new_path = format!("{dst}{}", &path[src.len()..]);
+        if let (Some(oid), Some(size)) = (file["lfs"]["oid"].as_str(), file["lfs"]["size"].as_u64()) {
+            operations.push(CommitOperation::AddLfsFile { path: new_path, oid: oid.to_string(), size });
+        } else {
+            let content = api.download_bytes(target.repo_type, repo_id, &target.revision, path).await?;
+            operations.push(CommitOperation::AddFile { path: new_path, content: STANDARD.encode(content) });
+        }
+    }
+    operations.push(if is_dir {
+        CommitOperation::DeleteFolder { path: src.to_string() }
+    } else {
+        CommitOperation::DeleteFile { path: src.to_string() }
+    });
+    Ok(operations)
+}
+
+fn format_commit(c: &Value) -> String {
+    let id = c["id"].as_str().unwrap_or_default();
+    let authors: Vec<_> = c["authors"].as_array()
+        .map(|a| a.iter().filter_map(|a| a["user"].as_str()).collect())
+        .unwrap_or_default();
+    format!(
+        "{} {} {} {}",
+        &id[..id.len().min(8)],
+        c["date"].as_str().unwrap_or_default(),
+        if authors.is_empty() { "-".to_string() } else { authors.join(",") },
+        c["title"].as_str().unwrap_or_default()
+    )
+}
+
+#[test]
+fn format_commit_line() {
+    let c = serde_json::json!({
+        "id": "0123456789abcdef",
+        "title": "Upload model",
+        "date": "2024-08-01T00:00:00.000Z",
+        "authors": [{"user": "alice"}]
+    });
+    assert_eq!(format_commit(&c), "01234567 2024-08-01T00:00:00.000Z alice Upload model");
+}
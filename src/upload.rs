@@ -0,0 +1,240 @@
+use std::error::Error;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use indicatif::ProgressBar;
+use reqwest::header::{ACCEPT, CONTENT_TYPE, ETAG};
+use ring::digest::SHA256;
+use serde_json::{json, Value};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::api::{CommitOperation, HubApi, RepoType, LFS_MEDIA_TYPE};
+use crate::bundle::{bytes_bar, list_files};
+use crate::diff::hash_file;
+use crate::http;
+
+/// Files per preupload / LFS batch request.
+const BATCH: usize = 256;
+/// Attempts for every part of a multipart upload.
+const PART_ATTEMPTS: u32 = 4;
+
+/// A local file to upload.
+struct LocalFile {
+    path_in_repo: String,
+    full: PathBuf,
+    size: u64,
+    sha256: String,
+}
+
+/// One part of a multipart upload.
+struct Part {
+    url: String,
+    offset: u64,
+    len: u64,
+}
+
+/// Parts of a multipart upload. The batch answer carries the `chunk_size` and
+/// one presigned url per part under numeric keys, `"1"` or zero padded `"00001"`.
+fn multipart_plan(header: &Value, size: u64) -> Result<Vec<Part>, Box<dyn Error>> {
+    let chunk_size = header["chunk_size"].as_u64()
+        .or_else(|| header["chunk_size"].as_str()?.parse().ok())
+        .filter(|c| *c > 0)
+        .ok_or("Multipart upload without a chunk_size")?;
+    let mut urls: Vec<(u64, &str)> = header.as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(key, _)| !key.is_empty() && key.bytes().all(|b| b.is_ascii_digit()))
+        .filter_map(|(key, url)| Some((key.parse().ok()?, url.as_str()?)))
+        .collect();
+    urls.sort_unstable();
+    if urls.len() as u64 != size.div_ceil(chunk_size) {
+        return Err(format!("Multipart upload has {} part url(s) for {} part(s)", urls.len(), size.div_ceil(chunk_size)).into());
+    }
+    Ok(urls.into_iter()
+        .enumerate()
+        .map(|(i, (_, url))| {
+            let offset = i as u64 * chunk_size;
+            Part { url: url.to_string(), offset, len: chunk_size.min(size - offset) }
+        })
+        .collect())
+}
+
+/// PUT one part, retrying with backoff; returns the part's ETag.
+async fn put_part(url: &str, chunk: &[u8], part: usize) -> Result<String, Box<dyn Error>> {
+    let mut attempt = 1;
+    loop {
+        let ret = async {
+            let resp = http::send(http::public_client().put(url).body(chunk.to_vec())).await?;
+            if !resp.status().is_success() {
+                return Err(format!("status {}", resp.status()).into());
+            }
+            resp.headers()
+                .get(ETAG)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+                .ok_or_else(|| Box::<dyn Error>::from("no ETag in the answer"))
+        }.await;
+        match ret {
+            Ok(etag) => return Ok(etag),
+            Err(e) if attempt < PART_ATTEMPTS => {
+                println!("Part {part} failed ({e}), retrying ({attempt}/{})...", PART_ATTEMPTS - 1);
+                tokio::time::sleep(Duration::from_secs(1 << attempt)).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(format!("Part {part} failed {PART_ATTEMPTS} times, last error: {e}").into()),
+        }
+    }
+}
+
+/// Upload parts one by one, then send their etags to the completion url.
+async fn multipart(file: &LocalFile, upload: &Value, bar: &ProgressBar) -> Result<(), Box<dyn Error>> {
+    let plan = multipart_plan(&upload["header"], file.size)?;
+    let href = upload["href"].as_str().ok_or("Multipart upload without completion url")?;
+    let mut src = File::open(&file.full).await?;
+    let mut parts = Vec::with_capacity(plan.len());
+    for (i, part) in plan.iter().enumerate() {
+        let mut chunk = vec![0u8; part.len as usize];
+        src.seek(SeekFrom::Start(part.offset)).await?;
+        src.read_exact(&mut chunk).await?;
+        let etag = put_part(&part.url, &chunk, i + 1).await?;
+        parts.push(json!({"partNumber": i + 1, "etag": etag}));
+        bar.inc(part.len);
+    }
+
+    let resp = http::send(http::public_client()
+        .post(href)
+        .header(ACCEPT, LFS_MEDIA_TYPE)
+        .header(CONTENT_TYPE, LFS_MEDIA_TYPE)
+        .body(json!({"oid": file.sha256, "parts": parts}).to_string())
+    ).await?;
+    if !resp.status().is_success() {
+        return Err(format!("Completing the upload of {} returned {}", file.path_in_repo, resp.status()).into());
+    }
+    Ok(())
+}
+
+/// Single PUT of the whole file, for objects the Hub did not split.
+async fn basic(file: &LocalFile, upload: &Value, bar: &ProgressBar) -> Result<(), Box<dyn Error>> {
+    let href = upload["href"].as_str().ok_or("LFS upload action without href")?;
+    let mut req = http::public_client().put(href).body(tokio::fs::read(&file.full).await?);
+    for (name, value) in upload["header"].as_object().into_iter().flatten() {
+        req = req.header(name, value.as_str().unwrap_or_default());
+    }
+    let resp = http::send(req).await?;
+    if !resp.status().is_success() {
+        return Err(format!("Uploading {} returned {}", file.path_in_repo, resp.status()).into());
+    }
+    bar.inc(file.size);
+    Ok(())
+}
+
+/// Local files under `local` with their path in the repo.
+fn collect(local: &Path, path_in_repo: Option<&str>) -> Result<Vec<(String, PathBuf)>, Box<dyn Error>> {
+    let prefix = path_in_repo.map(|p| p.trim_matches('/')).filter(|p| !p.is_empty());
+    if local.is_dir() {
+        Ok(list_files(local)?
+            .into_iter()
+            .map(|rel| {
+                let full = local.join(&rel);
+                (prefix.map_or(rel.clone(), |p| format!("{p}/{rel}")), full)
+            })
+            .collect())
+    } else {
+        let name = local.file_name().ok_or("Nothing to upload")?.to_string_lossy().to_string();
+        Ok(vec![(prefix.map_or(name, str::to_string), local.to_path_buf())])
+    }
+}
+
+/// Upload `local` (a file or a folder) and return the commit operations adding
+/// it at `path_in_repo`. LFS objects are pushed first, split into parts when
+/// the Hub asks for a multipart transfer; regular files go inline.
+pub async fn upload_operations(api: &HubApi, repo_type: RepoType, repo_id: &str, revision: &str, local: &Path, path_in_repo: Option<&str>) -> Result<Vec<CommitOperation>, Box<dyn Error>> {
+    let entries = collect(local, path_in_repo)?;
+    println!("Hashing {} file(s)...", entries.len());
+    let mut files = Vec::with_capacity(entries.len());
+    for (path_in_repo, full) in entries {
+        let size = std::fs::metadata(&full)?.len();
+        let sha256 = hash_file(&full, &SHA256, b"").await?;
+        files.push(LocalFile { path_in_repo, full, size, sha256 });
+    }
+
+    let mut modes = Vec::with_capacity(files.len());
+    for batch in files.chunks(BATCH) {
+        let mut samples = Vec::with_capacity(batch.len());
+        for file in batch {
+            let mut sample = vec![0u8; file.size.min(512) as usize];
+            File::open(&file.full).await?.read_exact(&mut sample).await?;
+            samples.push((file.path_in_repo.clone(), file.size, sample));
+        }
+        modes.extend(api.preupload(repo_type, repo_id, revision, &samples).await?);
+    }
+    let mode_of = |path: &str| modes.iter().find(|m| m["path"].as_str() == Some(path));
+
+    let mut operations = Vec::with_capacity(files.len());
+    let mut lfs = Vec::new();
+    for file in &files {
+        let mode = mode_of(&file.path_in_repo);
+        if mode.is_some_and(|m| m["shouldIgnore"].as_bool() == Some(true)) {
+            println!("Skip {}, ignored by the repo's .gitignore", file.path_in_repo);
+        } else if mode.is_some_and(|m| m["uploadMode"].as_str() == Some("lfs")) {
+            lfs.push(file);
+        } else {
+            let content = STANDARD.encode(tokio::fs::read(&file.full).await?);
+            operations.push(CommitOperation::AddFile { path: file.path_in_repo.clone(), content });
+        }
+    }
+
+    let bar = bytes_bar(lfs.iter().map(|f| f.size).sum(), "upload");
+    for batch in lfs.chunks(BATCH) {
+        let objects: Vec<_> = batch.iter().map(|f| (f.sha256.clone(), f.size)).collect();
+        for object in api.lfs_batch_upload(repo_type, repo_id, revision, &objects).await? {
+            let Some(file) = batch.iter().find(|f| object["oid"].as_str() == Some(&f.sha256)) else {
+                continue;
+            };
+            let upload = &object["actions"]["upload"];
+            if !upload.is_object() {
+                bar.println(format!("{} is already on the Hub", file.path_in_repo));
+                bar.inc(file.size);
+            } else if upload["header"]["chunk_size"].is_null() {
+                basic(file, upload, &bar).await?;
+            } else {
+                multipart(file, upload, &bar).await?;
+            }
+            if object["actions"]["verify"].is_object() {
+                api.lfs_verify(&object["actions"]["verify"], &file.sha256, file.size).await?;
+            }
+        }
+    }
+    bar.finish();
+
+    operations.extend(lfs.iter().map(|f| CommitOperation::AddLfsFile {
+        path: f.path_in_repo.clone(),
+        oid: f.sha256.clone(),
+        size: f.size,
+    }));
+    Ok(operations)
+}
+
+#[test]
+fn multipart_parts() {
+    let header = json!({"chunk_size": "10", "1": "https://s3/p1", "2": "https://s3/p2", "3": "https://s3/p3"});
+    let plan = multipart_plan(&header, 25).unwrap();
+    assert_eq!(plan.iter().map(|p| (p.url.as_str(), p.offset, p.len)).collect::<Vec<_>>(), [
+        ("https://s3/p1", 0, 10),
+        ("https://s3/p2", 10, 10),
+        ("https://s3/p3", 20, 5),
+    ]);
+    assert!(multipart_plan(&header, 35).is_err());
+
+    let padded = json!({"chunk_size": 10, "00002": "https://s3/p2", "00001": "https://s3/p1", "transfer": "multipart"});
+    let plan = multipart_plan(&padded, 15).unwrap();
+    assert_eq!(plan.iter().map(|p| (p.url.as_str(), p.offset, p.len)).collect::<Vec<_>>(), [
+        ("https://s3/p1", 0, 10),
+        ("https://s3/p2", 10, 5),
+    ]);
+    assert!(multipart_plan(&json!({"1": "https://s3/p1"}), 5).is_err());
+}